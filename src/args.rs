@@ -0,0 +1,918 @@
+use std::{iter::Peekable, str::Chars};
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum Error {
+    #[error("unexpected EOF while looking for matching `''")]
+    MissingEndSingleQuote,
+    #[error("unexpected EOF while looking for matching `\"'")]
+    MissingEndDoubleQuote,
+    #[error("unexpected EOF after `\\'")]
+    TrailingBackslash,
+    #[error("unexpected EOF after `|'")]
+    TrailingPipe,
+    #[error("syntax error near unexpected token `{0}'")]
+    UnexpectedToken(String),
+    #[error("here-document delimited by end-of-file (wanted `{0}')")]
+    MissingHereDocEnd(String),
+}
+
+impl Error {
+    /// The input is not wrong, only incomplete, and can be continued on the next line.
+    pub fn is_incomplete(&self) -> bool {
+        matches!(
+            self,
+            Self::MissingEndSingleQuote
+                | Self::MissingEndDoubleQuote
+                | Self::TrailingBackslash
+                | Self::TrailingPipe
+                | Self::MissingHereDocEnd(_)
+        )
+    }
+}
+
+/// How a part of a word was quoted, which decides the expansions applied to it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quoting {
+    /// Subject to every expansion.
+    Unquoted,
+    /// Taken literally, from single quotes or a backslash escape.
+    Quoted,
+    /// Only parameters are expanded, the result is not split or globbed.
+    DoubleQuoted,
+}
+
+/// A piece of a word together with the quoting it was written in.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Part {
+    pub text: String,
+    pub quoting: Quoting,
+}
+
+/// A single word of the input, before any expansion.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Word(pub Vec<Part>);
+
+impl Word {
+    /// The word with its quotes removed, but nothing expanded.
+    pub fn literal(&self) -> String {
+        self.0.iter().map(|part| part.text.as_str()).collect()
+    }
+
+    /// Splits a `NAME=value` word into the name and the value, which is
+    /// still to be expanded.
+    ///
+    /// The name and the `=` have to be unquoted, like in bash.
+    pub fn assignment(&self) -> Option<(String, Word)> {
+        let first = self.0.first().filter(|p| p.quoting == Quoting::Unquoted)?;
+        let (name, value) = first.text.split_once('=')?;
+        if !is_name(name) {
+            return None;
+        }
+        let mut parts = vec![Part {
+            text: value.to_string(),
+            quoting: Quoting::Unquoted,
+        }];
+        parts.extend(self.0[1..].iter().cloned());
+        Some((name.to_string(), Word(parts)))
+    }
+
+    /// Starts a new part, even if it ends up empty like `''`.
+    fn open(&mut self, quoting: Quoting) {
+        self.0.push(Part {
+            text: String::new(),
+            quoting,
+        });
+    }
+
+    fn push(&mut self, c: char, quoting: Quoting) {
+        match self.0.last_mut() {
+            Some(last) if last.quoting == quoting => last.text.push(c),
+            _ => self.0.push(Part {
+                text: c.to_string(),
+                quoting,
+            }),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    Word(Word),
+    /// `|`
+    Pipe,
+    /// An unquoted line break, which ends the pipeline.
+    Newline,
+    /// `;`, which ends the pipeline like a line break.
+    Semicolon,
+    /// `<<` or `<<-`, followed by the delimiter word.
+    HereDoc {
+        /// `<<-` removes the leading tabs of every line.
+        strip_tabs: bool,
+        /// The lines up to the delimiter, read after the next line break.
+        body: Word,
+    },
+    /// `<<<`, followed by the word to read.
+    HereString,
+    /// `>`, `>|`, `>>` or `>&`, optionally prefixed with the file descriptor
+    /// like `2>`, or `&>` and `&>>`
+    Redirect {
+        fd: Fd,
+        mode: Mode,
+    },
+}
+
+/// The file descriptors a redirect applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fd {
+    Single(u32),
+    /// `&>`, both stdout and stderr.
+    Both,
+}
+
+/// How a redirect opens its file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    /// `>`, refuses to overwrite an existing file with `noclobber`.
+    Truncate,
+    /// `>|`, truncates even with `noclobber`.
+    Clobber,
+    /// `>>`
+    Append,
+    /// `>&`, the target names a file descriptor to copy, like `2>&1`.
+    Duplicate,
+}
+
+impl Mode {
+    pub fn operator(&self) -> &'static str {
+        match self {
+            Self::Truncate => ">",
+            Self::Clobber => ">|",
+            Self::Append => ">>",
+            Self::Duplicate => ">&",
+        }
+    }
+}
+
+/// Sends a file descriptor of a command to a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Redirect {
+    pub fd: Fd,
+    pub mode: Mode,
+    /// The file, before any expansion.
+    pub target: Word,
+}
+
+/// A single command of a pipeline.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Command {
+    pub words: Vec<Word>,
+    /// In the order they were written, later ones win.
+    pub redirects: Vec<Redirect>,
+    /// What the command reads instead of its stdin, from a here-document or
+    /// a here-string.
+    pub stdin: Option<Word>,
+}
+
+impl Command {
+    fn is_empty(&self) -> bool {
+        self.words.is_empty() && self.redirects.is_empty() && self.stdin.is_none()
+    }
+}
+
+/// A valid variable name, letters, digits and `_` not starting with a digit.
+pub fn is_name(name: &str) -> bool {
+    name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Every command in a pipeline, in order.
+pub type Pipeline = Vec<Command>;
+
+/// Every pipeline of the input, split at line breaks and `;`, in order.
+pub type List = Vec<Pipeline>;
+
+/// Splits the input into its pipelines and those into their commands.
+pub fn parse_list(input: &str) -> Result<List, Error> {
+    let mut list = Vec::new();
+    let mut pipeline = Vec::new();
+    let mut command = Command::default();
+
+    let mut tokens = split_words(input)?.into_iter();
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Word(word) => command.words.push(word),
+            Token::Pipe if command.is_empty() => {
+                return Err(Error::UnexpectedToken("|".to_string()))
+            }
+            Token::Pipe => pipeline.push(std::mem::take(&mut command)),
+            // blank lines, and the line break after a `|` which continues the pipeline
+            Token::Newline if command.is_empty() => {}
+            // unlike a line break, nothing can come before it
+            Token::Semicolon if command.is_empty() => {
+                return Err(Error::UnexpectedToken(";".to_string()))
+            }
+            Token::Newline | Token::Semicolon => {
+                pipeline.push(std::mem::take(&mut command));
+                list.push(std::mem::take(&mut pipeline));
+            }
+            Token::Redirect { fd, mode } => match tokens.next() {
+                Some(Token::Word(target)) => command.redirects.push(Redirect { fd, mode, target }),
+                other => return Err(unexpected(other)),
+            },
+            Token::HereDoc { body, .. } => match tokens.next() {
+                // the delimiter was only needed to find the end of the body
+                Some(Token::Word(_)) => command.stdin = Some(body),
+                other => return Err(unexpected(other)),
+            },
+            Token::HereString => match tokens.next() {
+                // like bash, the word ends up on a line of its own
+                Some(Token::Word(mut word)) => {
+                    word.push('\n', Quoting::Quoted);
+                    command.stdin = Some(word);
+                }
+                other => return Err(unexpected(other)),
+            },
+        }
+    }
+
+    if command.is_empty() && !pipeline.is_empty() {
+        return Err(Error::TrailingPipe);
+    }
+    if !command.is_empty() {
+        pipeline.push(command);
+        list.push(pipeline);
+    }
+
+    Ok(list)
+}
+
+/// The error for a token where the target of a redirect was expected.
+fn unexpected(token: Option<Token>) -> Error {
+    let token = match token {
+        Some(Token::Word(_)) => unreachable!("a word is always expected"),
+        Some(Token::Pipe) => "|",
+        Some(Token::Semicolon) => ";",
+        Some(Token::Redirect { mode, .. }) => mode.operator(),
+        Some(Token::HereDoc { strip_tabs, .. }) => {
+            if strip_tabs {
+                "<<-"
+            } else {
+                "<<"
+            }
+        }
+        Some(Token::HereString) => "<<<",
+        Some(Token::Newline) | None => "newline",
+    };
+    Error::UnexpectedToken(token.to_string())
+}
+
+/// Splits the input into words and operators, keeping track of how each
+/// part of a word was quoted.
+pub fn split_words(input: &str) -> Result<Vec<Token>, Error> {
+    let mut words = Vec::new();
+    let mut current = Word::default();
+    // the here-documents whose body starts after the next line break
+    let mut here_docs = Vec::new();
+
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' => {
+                if !current.0.is_empty() {
+                    words.push(Token::Word(std::mem::take(&mut current)));
+                }
+            }
+            '\n' => {
+                if !current.0.is_empty() {
+                    words.push(Token::Word(std::mem::take(&mut current)));
+                }
+                for i in here_docs.drain(..) {
+                    // without a delimiter, parsing reports the missing word
+                    let (Some(Token::HereDoc { strip_tabs, .. }), Some(Token::Word(delimiter))) =
+                        (words.get(i), words.get(i + 1))
+                    else {
+                        continue;
+                    };
+                    let read = here_doc_body(&mut chars, delimiter, *strip_tabs)?;
+                    if let Some(Token::HereDoc { body, .. }) = words.get_mut(i) {
+                        *body = read;
+                    }
+                }
+                words.push(Token::Newline);
+            }
+            '<' if chars.next_if_eq(&'<').is_some() => {
+                if !current.0.is_empty() {
+                    words.push(Token::Word(std::mem::take(&mut current)));
+                }
+                if chars.next_if_eq(&'<').is_some() {
+                    words.push(Token::HereString);
+                    continue;
+                }
+                here_docs.push(words.len());
+                words.push(Token::HereDoc {
+                    strip_tabs: chars.next_if_eq(&'-').is_some(),
+                    body: Word::default(),
+                });
+            }
+            // a comment only starts at the beginning of a word, and runs to the end of the line
+            '#' if current.0.is_empty() => while chars.next_if(|c| *c != '\n').is_some() {},
+            '|' => {
+                if !current.0.is_empty() {
+                    words.push(Token::Word(std::mem::take(&mut current)));
+                }
+                words.push(Token::Pipe);
+            }
+            ';' => {
+                if !current.0.is_empty() {
+                    words.push(Token::Word(std::mem::take(&mut current)));
+                }
+                words.push(Token::Semicolon);
+            }
+            '>' => {
+                // digits right in front of the operator name the file descriptor
+                let fd = match current.0.as_slice() {
+                    [Part {
+                        text,
+                        quoting: Quoting::Unquoted,
+                    }] if text.bytes().all(|b| b.is_ascii_digit()) => text.parse().ok(),
+                    _ => None,
+                };
+                match fd {
+                    Some(_) => current = Word::default(),
+                    None if !current.0.is_empty() => {
+                        words.push(Token::Word(std::mem::take(&mut current)))
+                    }
+                    None => {}
+                }
+                let mode = match chars.next_if(|c| matches!(c, '>' | '|' | '&')) {
+                    Some('>') => Mode::Append,
+                    Some('&') => Mode::Duplicate,
+                    Some(_) => Mode::Clobber,
+                    None => Mode::Truncate,
+                };
+                words.push(Token::Redirect {
+                    fd: Fd::Single(fd.unwrap_or(1)),
+                    mode,
+                });
+            }
+            '&' if chars.next_if_eq(&'>').is_some() => {
+                if !current.0.is_empty() {
+                    words.push(Token::Word(std::mem::take(&mut current)));
+                }
+                let mode = match chars.next_if_eq(&'>') {
+                    Some(_) => Mode::Append,
+                    None => Mode::Truncate,
+                };
+                words.push(Token::Redirect { fd: Fd::Both, mode });
+            }
+            '\\' => match chars.next() {
+                // line continuation
+                Some('\n') if chars.peek().is_none() => return Err(Error::TrailingBackslash),
+                Some('\n') => {}
+                Some(escaped) => current.push(escaped, Quoting::Quoted),
+                None => return Err(Error::TrailingBackslash),
+            },
+            // ANSI-C quoting like `$'a\tb'`
+            '$' if chars.next_if_eq(&'\'').is_some() => {
+                current.open(Quoting::Quoted);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some('\\') => match chars.next() {
+                            Some(escaped) => ansi_c_escape(escaped, &mut chars, &mut current),
+                            None => return Err(Error::MissingEndSingleQuote),
+                        },
+                        Some(c) => current.push(c, Quoting::Quoted),
+                        None => return Err(Error::MissingEndSingleQuote),
+                    }
+                }
+            }
+            '\'' => {
+                current.open(Quoting::Quoted);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => current.push(c, Quoting::Quoted),
+                        None => return Err(Error::MissingEndSingleQuote),
+                    }
+                }
+            }
+            '"' => {
+                current.open(Quoting::DoubleQuoted);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('\n') => {}
+                            Some(escaped @ ('\\' | '"' | '$' | '`')) => {
+                                current.push(escaped, Quoting::Quoted)
+                            }
+                            Some(other) => {
+                                current.push('\\', Quoting::DoubleQuoted);
+                                current.push(other, Quoting::DoubleQuoted);
+                            }
+                            None => return Err(Error::MissingEndDoubleQuote),
+                        },
+                        Some('$') if chars.peek() == Some(&'{') => {
+                            push_braced(&mut chars, &mut current, Quoting::DoubleQuoted)
+                        }
+                        Some(c) => current.push(c, Quoting::DoubleQuoted),
+                        None => return Err(Error::MissingEndDoubleQuote),
+                    }
+                }
+            }
+            // `${...}` stays within the word, even with blanks in the word of an operator
+            '$' if chars.peek() == Some(&'{') => {
+                push_braced(&mut chars, &mut current, Quoting::Unquoted)
+            }
+            _ => current.push(c, Quoting::Unquoted),
+        }
+    }
+
+    // quotes start a word even if nothing ends up inside of them
+    if !current.0.is_empty() {
+        words.push(Token::Word(current));
+    }
+    // the body is still to come on the next lines
+    if let Some(Token::Word(delimiter)) = here_docs.first().and_then(|i| words.get(i + 1)) {
+        return Err(Error::MissingHereDocEnd(delimiter.literal()));
+    }
+
+    Ok(words)
+}
+
+/// Moves a `${...}` to the word as typed, `chars` is right after the `$`.
+///
+/// The quotes in the word of an operator are only removed by the expansion.
+fn push_braced(chars: &mut Peekable<Chars>, word: &mut Word, quoting: Quoting) {
+    let mut inner = String::new();
+    chars.next();
+    let closed = read_braced(chars, &mut inner);
+    word.push('$', quoting);
+    word.push('{', quoting);
+    for c in inner.chars() {
+        word.push(c, quoting);
+    }
+    if closed {
+        word.push('}', quoting);
+    }
+}
+
+/// Moves the inside of a `${...}` from `chars` to `inner`, up to the closing
+/// brace, which is consumed but not kept.
+///
+/// Quotes and backslashes are kept as typed, but a brace within them doesn't
+/// count. Returns whether the closing brace was found.
+pub fn read_braced(chars: &mut Peekable<Chars>, inner: &mut String) -> bool {
+    let mut depth = 0;
+    let mut quote = None;
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => {
+                inner.push(c);
+                if let Some(escaped) = chars.next() {
+                    inner.push(escaped);
+                }
+                continue;
+            }
+            // a nested `${...}` has quotes of its own
+            (_, '$') if chars.peek() == Some(&'{') => {
+                chars.next();
+                inner.push_str("${");
+                if read_braced(chars, inner) {
+                    inner.push('}');
+                }
+                continue;
+            }
+            (None, '\'' | '"') => quote = Some(c),
+            (Some('"'), '"') => quote = None,
+            (None, '{') => depth += 1,
+            (None, '}') if depth == 0 => return true,
+            (None, '}') => depth -= 1,
+            _ => {}
+        }
+        inner.push(c);
+    }
+    false
+}
+
+/// Reads the lines of a here-document up to the line with only the delimiter.
+///
+/// Like bash, quoting any part of the delimiter keeps the body from being
+/// expanded, otherwise a backslash only escapes `$`, `` ` `` and itself.
+fn here_doc_body(
+    chars: &mut Peekable<Chars>,
+    delimiter: &Word,
+    strip_tabs: bool,
+) -> Result<Word, Error> {
+    let quoted = delimiter.0.iter().any(|p| p.quoting != Quoting::Unquoted);
+    let quoting = if quoted {
+        Quoting::Quoted
+    } else {
+        Quoting::DoubleQuoted
+    };
+    let delimiter = delimiter.literal();
+
+    let mut body = Word::default();
+    body.open(quoting);
+    loop {
+        let mut line = String::new();
+        let mut ended = false;
+        for c in chars.by_ref() {
+            if c == '\n' {
+                ended = true;
+                break;
+            }
+            line.push(c);
+        }
+        let line = if strip_tabs {
+            line.trim_start_matches('\t')
+        } else {
+            &line
+        };
+        if line == delimiter {
+            return Ok(body);
+        }
+        if !ended {
+            return Err(Error::MissingHereDocEnd(delimiter));
+        }
+
+        let mut line = line.chars();
+        while let Some(c) = line.next() {
+            match c {
+                '\\' if !quoted => match line.next() {
+                    Some(escaped @ ('\\' | '$' | '`')) => body.push(escaped, Quoting::Quoted),
+                    Some(other) => {
+                        body.push('\\', quoting);
+                        body.push(other, quoting);
+                    }
+                    None => body.push('\\', quoting),
+                },
+                _ => body.push(c, quoting),
+            }
+        }
+        body.push('\n', quoting);
+    }
+}
+
+/// Decodes the escape following a backslash within `$'...'`.
+///
+/// Unknown escapes are kept as they are, backslash included.
+fn ansi_c_escape(escaped: char, chars: &mut Peekable<Chars>, word: &mut Word) {
+    let decoded = match escaped {
+        'a' => Some('\x07'),
+        'b' => Some('\x08'),
+        'e' | 'E' => Some('\x1b'),
+        'f' => Some('\x0c'),
+        'n' => Some('\n'),
+        'r' => Some('\r'),
+        't' => Some('\t'),
+        'v' => Some('\x0b'),
+        '\\' | '\'' | '"' | '?' => Some(escaped),
+        'x' => radix(chars, 16, 2),
+        'u' => radix(chars, 16, 4),
+        'U' => radix(chars, 16, 8),
+        '0'..='7' => {
+            let digit = escaped.to_digit(8).unwrap_or_default();
+            let rest = digits(chars, 8, 2);
+            let value = rest.iter().fold(digit, |value, d| {
+                value * 8 + d.to_digit(8).unwrap_or_default()
+            });
+            char::from_u32(value & 0xff)
+        }
+        _ => None,
+    };
+
+    match decoded {
+        Some(c) => word.push(c, Quoting::Quoted),
+        None => {
+            word.push('\\', Quoting::Quoted);
+            word.push(escaped, Quoting::Quoted);
+        }
+    }
+}
+
+/// Reads up to `max` digits of the radix as a character.
+///
+/// `None` without any digit, or when they don't name a character like a
+/// surrogate, and then the digits are left to be read as they are.
+fn radix(chars: &mut Peekable<Chars>, radix: u32, max: usize) -> Option<char> {
+    let mut ahead = chars.clone();
+    let digits = digits(&mut ahead, radix, max);
+    if digits.is_empty() {
+        return None;
+    }
+    let value = digits.iter().fold(0, |value, d| {
+        value * radix + d.to_digit(radix).unwrap_or_default()
+    });
+    let c = char::from_u32(value)?;
+    *chars = ahead;
+    Some(c)
+}
+
+fn digits(chars: &mut Peekable<Chars>, radix: u32, max: usize) -> Vec<char> {
+    let mut digits = Vec::with_capacity(max);
+    while digits.len() < max {
+        match chars.next_if(|c| c.is_digit(radix)) {
+            Some(d) => digits.push(d),
+            None => break,
+        }
+    }
+    digits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The words of every command of every pipeline, as typed.
+    fn literals(input: &str) -> Vec<Vec<Vec<String>>> {
+        parse_list(input)
+            .unwrap()
+            .iter()
+            .map(|pipeline| {
+                pipeline
+                    .iter()
+                    .map(|command| command.words.iter().map(Word::literal).collect())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn semicolon_separates_pipelines() {
+        assert_eq!(
+            literals("echo a; echo b | cat;echo c;"),
+            [
+                vec![vec!["echo", "a"]],
+                vec![vec!["echo", "b"], vec!["cat"]],
+                vec![vec!["echo", "c"]],
+            ]
+        );
+        assert_eq!(
+            literals(r#"echo "a;b" 'c;d' e\;f"#),
+            [vec![vec!["echo", "a;b", "c;d", "e;f"]]]
+        );
+    }
+
+    #[test]
+    fn semicolon_needs_a_command_before_it() {
+        for input in [";", "echo a;;", "echo a | ; echo b", "\n; echo"] {
+            assert_eq!(
+                parse_list(input),
+                Err(Error::UnexpectedToken(";".to_string())),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn lines_separate_pipelines() {
+        assert_eq!(
+            literals("a | b\n\nc |\nd\n"),
+            [vec![vec!["a"], vec!["b"]], vec![vec!["c"], vec!["d"]]]
+        );
+        assert_eq!(
+            literals("echo a\necho b; echo c\n"),
+            [
+                vec![vec!["echo", "a"]],
+                vec![vec!["echo", "b"]],
+                vec![vec!["echo", "c"]],
+            ]
+        );
+        assert_eq!(literals("  \n\n"), Vec::<Vec<Vec<String>>>::new());
+    }
+
+    #[test]
+    fn incomplete_input() {
+        for (input, error) in [
+            ("echo 'a", Error::MissingEndSingleQuote),
+            ("echo \"a", Error::MissingEndDoubleQuote),
+            ("echo a\\", Error::TrailingBackslash),
+            ("echo a |", Error::TrailingPipe),
+        ] {
+            assert!(error.is_incomplete());
+            assert_eq!(parse_list(input), Err(error), "{}", input);
+        }
+        // completed on the next line
+        assert_eq!(
+            literals("echo 'a\nb' c\\\nd"),
+            [vec![vec!["echo", "a\nb", "cd"]]]
+        );
+        assert_eq!(
+            literals("echo a |\ncat"),
+            [vec![vec!["echo", "a"], vec!["cat"]]]
+        );
+
+        // a pipe that is missing its first command is an error
+        for input in ["| a", "a | | b"] {
+            let error = parse_list(input).unwrap_err();
+            assert_eq!(error, Error::UnexpectedToken("|".to_string()), "{}", input);
+            assert!(!error.is_incomplete());
+        }
+    }
+
+    #[test]
+    fn comments() {
+        assert_eq!(literals("echo a#b # comment"), [vec![vec!["echo", "a#b"]]]);
+        assert_eq!(
+            literals("echo '#' \"#\" \\# #"),
+            [vec![vec!["echo", "#", "#", "#"]]]
+        );
+        assert_eq!(
+            literals("# only a comment\necho a # | cat\n"),
+            [vec![vec!["echo", "a"]]]
+        );
+    }
+
+    #[test]
+    fn redirects() {
+        let list = parse_list("cmd 2>err >>out arg 3>&1 >|x").unwrap();
+        let command = &list[0][0];
+        assert_eq!(
+            command.words.iter().map(Word::literal).collect::<Vec<_>>(),
+            ["cmd", "arg"]
+        );
+        let redirects: Vec<_> = command
+            .redirects
+            .iter()
+            .map(|r| (r.fd, r.mode, r.target.literal()))
+            .collect();
+        assert_eq!(
+            redirects,
+            [
+                (Fd::Single(2), Mode::Truncate, "err".to_string()),
+                (Fd::Single(1), Mode::Append, "out".to_string()),
+                (Fd::Single(3), Mode::Duplicate, "1".to_string()),
+                (Fd::Single(1), Mode::Clobber, "x".to_string()),
+            ]
+        );
+        // quoted digits are an argument, not a descriptor
+        let list = parse_list("echo '2'>out").unwrap();
+        assert_eq!(list[0][0].words.len(), 2);
+        assert_eq!(list[0][0].redirects[0].fd, Fd::Single(1));
+
+        for (input, token) in [
+            ("echo >", "newline"),
+            ("echo > | a", "|"),
+            ("echo > >> a", ">>"),
+        ] {
+            assert_eq!(
+                parse_list(input),
+                Err(Error::UnexpectedToken(token.to_string())),
+                "{}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn combined_redirects() {
+        let list = parse_list("cmd &>both &>>more").unwrap();
+        let redirects: Vec<_> = list[0][0]
+            .redirects
+            .iter()
+            .map(|r| (r.fd, r.mode, r.target.literal()))
+            .collect();
+        assert_eq!(
+            redirects,
+            [
+                (Fd::Both, Mode::Truncate, "both".to_string()),
+                (Fd::Both, Mode::Append, "more".to_string()),
+            ]
+        );
+    }
+
+    fn part(text: &str, quoting: Quoting) -> Part {
+        Part {
+            text: text.to_string(),
+            quoting,
+        }
+    }
+
+    #[test]
+    fn split_words_keeps_the_quoting() {
+        let tokens = split_words(r#"a'b c'"d $e"\ f "" "#).unwrap();
+        assert_eq!(
+            tokens,
+            [
+                Token::Word(Word(vec![
+                    part("a", Quoting::Unquoted),
+                    part("b c", Quoting::Quoted),
+                    part("d $e", Quoting::DoubleQuoted),
+                    part(" ", Quoting::Quoted),
+                    part("f", Quoting::Unquoted),
+                ])),
+                Token::Word(Word(vec![part("", Quoting::DoubleQuoted)])),
+            ]
+        );
+        assert_eq!(
+            split_words(r#""a\"b\c""#).unwrap(),
+            [Token::Word(Word(vec![
+                part("a", Quoting::DoubleQuoted),
+                part("\"", Quoting::Quoted),
+                part("b\\c", Quoting::DoubleQuoted),
+            ]))]
+        );
+        // an empty quoted string is still an argument
+        assert_eq!(
+            literals("echo '' \"\" a''"),
+            [vec![vec!["echo", "", "", "a"]]]
+        );
+    }
+
+    #[test]
+    fn ansi_c_quoting() {
+        assert_eq!(
+            split_words("$'a\\tb'").unwrap(),
+            [Token::Word(Word(vec![part("a\tb", Quoting::Quoted)]))]
+        );
+        for (input, expected) in [
+            (r"$'\x41\x4a2é\U0001F600'", "AJ2é😀"),
+            (r"$'\101\0618'", "A18"),
+            (r"$'\'\\\q'", r"'\\q"),
+            (r"$'\x'", r"\x"),
+            (r"$'a'b$'c'", "abc"),
+            // not a character, so kept as typed
+            (r"$'\uD800'", r"\uD800"),
+            (r"$'\U00110000x'", r"\U00110000x"),
+        ] {
+            assert_eq!(literals(input), [vec![vec![expected]]], "{}", input);
+        }
+        // only outside of quotes
+        assert_eq!(literals(r#""$'\t'""#), [vec![vec![r"$'\t'"]]]);
+    }
+
+    #[test]
+    fn assignments() {
+        let words = &parse_list("A=1 B='x y'z C\"=\"2 =3 1A=4").unwrap()[0][0].words;
+        let assignments: Vec<_> = words
+            .iter()
+            .map(|word| {
+                word.assignment()
+                    .map(|(name, value)| (name, value.literal()))
+            })
+            .collect();
+        assert_eq!(
+            assignments,
+            [
+                Some(("A".to_string(), "1".to_string())),
+                Some(("B".to_string(), "x yz".to_string())),
+                None,
+                None,
+                None,
+            ]
+        );
+        assert!(is_name("_a1") && !is_name("1a") && !is_name("") && !is_name("a-b"));
+    }
+
+    #[test]
+    fn here_documents() {
+        let list = parse_list("cat <<EOF | cat <<-'END'\na $x\nEOF\n\tb\nEND\necho after").unwrap();
+        let [first, second] = list[0].as_slice() else {
+            panic!("{:?}", list);
+        };
+        assert_eq!(first.stdin.as_ref().unwrap().literal(), "a $x\n");
+        assert_eq!(
+            first.stdin.as_ref().unwrap().0[0].quoting,
+            Quoting::DoubleQuoted
+        );
+        assert_eq!(second.stdin.as_ref().unwrap().literal(), "b\n");
+        assert_eq!(second.stdin.as_ref().unwrap().0[0].quoting, Quoting::Quoted);
+        assert_eq!(list[1][0].words[0].literal(), "echo");
+
+        let error = parse_list("cat <<EOF\nbody").unwrap_err();
+        assert_eq!(error, Error::MissingHereDocEnd("EOF".to_string()));
+        assert!(error.is_incomplete());
+    }
+
+    #[test]
+    fn here_strings() {
+        let list = parse_list("cat <<< 'a b'").unwrap();
+        assert_eq!(list[0][0].stdin.as_ref().unwrap().literal(), "a b\n");
+        assert_eq!(list[0][0].words.len(), 1);
+        assert_eq!(
+            parse_list("cat <<< | a"),
+            Err(Error::UnexpectedToken("|".to_string()))
+        );
+        assert_eq!(
+            parse_list("cat << <<< a"),
+            Err(Error::UnexpectedToken("<<<".to_string()))
+        );
+    }
+
+    #[test]
+    fn braces_keep_the_operator_word() {
+        assert_eq!(
+            literals("echo ${X:-a b}c"),
+            [vec![vec!["echo", "${X:-a b}c"]]]
+        );
+        assert_eq!(
+            literals(r#"echo ${X:-"}" '}'} d"#),
+            [vec![vec!["echo", r#"${X:-"}" '}'}"#, "d"]]]
+        );
+    }
+}
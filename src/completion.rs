@@ -0,0 +1,188 @@
+use std::{
+    collections::BTreeMap,
+    ops::Bound,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use crate::Builtins;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EntryKind {
+    Builtin,
+    Program(PathBuf),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub name: String,
+    pub kind: EntryKind,
+}
+
+/// The programs of a single `PATH` directory, as of its last modification.
+struct Directory {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    programs: Vec<(String, PathBuf)>,
+}
+
+/// All the commands the shell knows about, keyed by their name.
+pub struct Completion {
+    entries: BTreeMap<String, Entry>,
+    /// The `PATH` the entries were built from.
+    path: String,
+    directories: Vec<Directory>,
+}
+
+impl Completion {
+    /// Knows the builtins and the programs in the directories of `path`.
+    pub fn new(path: &str) -> Self {
+        let mut completion = Self {
+            entries: BTreeMap::new(),
+            path: String::new(),
+            directories: Vec::new(),
+        };
+        completion.refresh(path);
+        completion
+    }
+
+    /// Rescans the directories of `path`, the value of `PATH`, that changed
+    /// since the last scan.
+    ///
+    /// A directory counts as changed if it is new to `PATH` or its
+    /// modification time moved, which happens whenever a file is added or
+    /// removed. Without a `PATH` only the builtins are known.
+    pub fn refresh(&mut self, path: &str) {
+        let mut old = std::mem::take(&mut self.directories);
+        let mut changed = path != self.path || self.entries.is_empty();
+
+        for dir in path
+            .split(':')
+            .map(str::trim)
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+        {
+            let modified = std::fs::metadata(&dir).and_then(|m| m.modified()).ok();
+            let cached = old
+                .iter()
+                .position(|d| d.path == dir && d.modified == modified)
+                .map(|i| old.swap_remove(i));
+
+            let directory = match cached {
+                Some(directory) => directory,
+                None => {
+                    changed = true;
+                    Directory {
+                        programs: generate_program_names(&dir),
+                        path: dir,
+                        modified,
+                    }
+                }
+            };
+            self.directories.push(directory);
+        }
+
+        self.path = path.to_string();
+        if changed || !old.is_empty() {
+            self.rebuild();
+        }
+    }
+
+    fn rebuild(&mut self) {
+        self.entries.clear();
+
+        for name in Builtins::NAMES {
+            self.entries.insert(
+                name.to_string(),
+                Entry {
+                    name: name.to_string(),
+                    kind: EntryKind::Builtin,
+                },
+            );
+        }
+
+        // the first program found in PATH shadows the later ones
+        for (name, path) in self.directories.iter().flat_map(|d| &d.programs) {
+            self.entries.entry(name.clone()).or_insert(Entry {
+                name: name.clone(),
+                kind: EntryKind::Program(path.clone()),
+            });
+        }
+    }
+
+    /// Iterates over every builtin and program, sorted by name.
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.values()
+    }
+
+    /// Iterates over the entries whose name starts with `prefix`, sorted by name.
+    pub fn predictive_search<'a>(&'a self, prefix: &'a str) -> impl Iterator<Item = &'a Entry> {
+        self.entries
+            .range::<str, _>((Bound::Included(prefix), Bound::Unbounded))
+            .take_while(move |(name, _)| name.starts_with(prefix))
+            .map(|(_, entry)| entry)
+    }
+
+    /// The sorted command names completing `word`, the word under the cursor.
+    ///
+    /// A name is only listed once, even if it is both a builtin and a
+    /// program or shows up in several `PATH` directories.
+    pub fn complete_word(&self, word: &str) -> Vec<String> {
+        self.predictive_search(word)
+            .map(|entry| entry.name.clone())
+            .collect()
+    }
+}
+
+fn generate_program_names(dir: &Path) -> Vec<(String, PathBuf)> {
+    let mut programs = Vec::new();
+
+    // a directory we can't read doesn't hold any program we could run
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return programs;
+    };
+
+    for entry in entries.filter_map(Result::ok) {
+        // also skips symlinks whose target is missing
+        if !is_executable(&entry.path()) {
+            continue;
+        }
+        // a name that isn't valid UTF-8 can't be typed or completed, the rest of the directory can
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        programs.push((name, entry.path()));
+    }
+
+    programs
+}
+
+/// The sorted names of the variables starting with `prefix`.
+pub fn complete_variable<'a>(prefix: &str, names: impl Iterator<Item = &'a String>) -> Vec<String> {
+    let mut names: Vec<_> = names
+        .filter(|name| name.starts_with(prefix))
+        .cloned()
+        .collect();
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Whether the path is a regular file the user may run, following symlinks.
+pub fn is_executable(path: &Path) -> bool {
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return false;
+    };
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+
+        metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+    }
+
+    #[cfg(not(unix))]
+    {
+        metadata.is_file()
+    }
+}
@@ -0,0 +1,1269 @@
+//! Turns the words of a command line into the final arguments.
+//!
+//! The stages run in the order bash uses: brace, tilde, parameter, word
+//! splitting, globbing and finally quote removal. Each stage only ever looks
+//! at the output of the previous one, so the result of an expansion is never
+//! expanded again.
+
+use std::path::{Path, PathBuf};
+
+use crate::{
+    args::{self, Part, Quoting, Word},
+    State,
+};
+
+/// The most words a single word may turn into through brace expansion, when
+/// `BRACE_LIMIT` is not set or not a valid number.
+pub const DEFAULT_BRACE_LIMIT: usize = 100_000;
+
+/// The most files a single pattern may match, when `GLOB_LIMIT` is not set or
+/// not a valid number.
+pub const DEFAULT_GLOB_LIMIT: usize = 100_000;
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum Error {
+    #[error("{0}: bad substitution")]
+    BadSubstitution(String),
+    #[error("{0}: {1}")]
+    ParameterNotSet(String, String),
+    #[error("{0}: brace expansion produces more than {1} words")]
+    TooManyWords(String, usize),
+    #[error("{0}: pattern matches more than {1} files")]
+    TooManyMatches(String, usize),
+}
+
+/// Which expansions are applied, all of them by default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stages {
+    pub brace: bool,
+    pub tilde: bool,
+    pub parameter: bool,
+    pub split: bool,
+    pub glob: bool,
+}
+
+impl Default for Stages {
+    fn default() -> Self {
+        Self {
+            brace: true,
+            tilde: true,
+            parameter: true,
+            split: true,
+            glob: true,
+        }
+    }
+}
+
+/// Where a character came from, which decides if it is split or globbed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Origin {
+    /// Typed without any quotes.
+    Literal,
+    /// Quoted, or the result of an expansion within double quotes.
+    Quoted,
+    /// The result of an unquoted expansion.
+    Expanded,
+}
+
+/// A word in between the stages, quote removal turns it into a `String`.
+type Field = Vec<(char, Origin)>;
+
+/// Expands the words with every stage enabled.
+pub fn expand(words: &[Word], state: &mut State) -> Result<Vec<String>, Error> {
+    expand_with(words, state, Stages::default())
+}
+
+pub fn expand_with(
+    words: &[Word],
+    state: &mut State,
+    stages: Stages,
+) -> Result<Vec<String>, Error> {
+    let mut args = Vec::with_capacity(words.len());
+    let brace_limit = limit(state, "BRACE_LIMIT", DEFAULT_BRACE_LIMIT);
+
+    for word in words {
+        let braced = if stages.brace {
+            braces(word, brace_limit)?
+        } else {
+            vec![word.clone()]
+        };
+        for word in &braced {
+            expand_field(word, state, stages, &mut args)?;
+        }
+    }
+
+    Ok(args)
+}
+
+/// The number in the variable, or `default` when it is unset or not a number.
+fn limit(state: &State, name: &str, default: usize) -> usize {
+    state
+        .var(name)
+        .and_then(|limit| limit.parse().ok())
+        .unwrap_or(default)
+}
+
+/// Expands the value of a `NAME=value` assignment, which like in bash is
+/// neither split nor globbed.
+pub fn expand_value(value: &Word, state: &mut State) -> Result<String, Error> {
+    let stages = Stages {
+        brace: false,
+        split: false,
+        glob: false,
+        ..Stages::default()
+    };
+    let mut value = expand_with(std::slice::from_ref(value), state, stages)?;
+    Ok(value.pop().unwrap_or_default())
+}
+
+/// Runs a word through every stage after the brace expansion.
+fn expand_field(
+    word: &Word,
+    state: &mut State,
+    stages: Stages,
+    args: &mut Vec<String>,
+) -> Result<(), Error> {
+    let field = expand_word(word, state, stages)?;
+
+    let fields = if stages.split {
+        let mut fields = split(field, state);
+        // a word like `""` stays, even though it is empty
+        if fields.is_empty() && word.0.iter().any(|p| p.quoting != Quoting::Unquoted) {
+            fields.push(Field::new());
+        }
+        fields
+    } else {
+        vec![field]
+    };
+
+    for field in fields {
+        let globbed = if stages.glob && is_pattern(&field) {
+            let glob_limit = limit(state, "GLOB_LIMIT", DEFAULT_GLOB_LIMIT);
+            glob(&field, &state.path, glob_limit)
+                .ok_or_else(|| Error::TooManyMatches(word.literal(), glob_limit))?
+        } else {
+            Vec::new()
+        };
+
+        if globbed.is_empty() {
+            args.push(field.iter().map(|(c, _)| c).collect());
+        } else {
+            args.extend(globbed);
+        }
+    }
+
+    Ok(())
+}
+
+/// A word in the brace stage, every character still knows how it was quoted.
+type Braced = Vec<(char, Quoting)>;
+
+/// Expands the `{a,b}` lists and `{1..5}` ranges of the word.
+///
+/// This is purely textual, the words don't have to exist as files.
+fn braces(word: &Word, limit: usize) -> Result<Vec<Word>, Error> {
+    let has_brace = word
+        .0
+        .iter()
+        .any(|part| part.quoting == Quoting::Unquoted && part.text.contains('{'));
+    if !has_brace {
+        return Ok(vec![word.clone()]);
+    }
+
+    let chars = word
+        .0
+        .iter()
+        .flat_map(|part| part.text.chars().map(|c| (c, part.quoting)))
+        .collect();
+    let mut words = Vec::new();
+    if brace_expand(chars, limit, &mut words).is_none() {
+        return Err(Error::TooManyWords(word.literal(), limit));
+    }
+
+    Ok(words.into_iter().map(|chars| to_word(&chars)).collect())
+}
+
+/// Expands the first brace of `chars` and then the rest of each result.
+///
+/// Returns `None` once more than `limit` words are produced.
+fn brace_expand(chars: Braced, limit: usize, words: &mut Vec<Braced>) -> Option<()> {
+    let Some((open, close, alternatives)) = find_brace(&chars, limit) else {
+        if words.len() >= limit {
+            return None;
+        }
+        words.push(chars);
+        return Some(());
+    };
+
+    for alternative in alternatives {
+        let mut word = chars[..open].to_vec();
+        word.extend(alternative);
+        word.extend_from_slice(&chars[close + 1..]);
+        brace_expand(word, limit, words)?;
+    }
+    Some(())
+}
+
+/// Finds the first valid brace, returning where it opens and closes and what it expands to.
+///
+/// Braces without a comma or a range, like `{a}`, stay as they are.
+fn find_brace(chars: &[(char, Quoting)], limit: usize) -> Option<(usize, usize, Vec<Braced>)> {
+    let unquoted = |i: usize, c: char| chars[i] == (c, Quoting::Unquoted);
+
+    for open in 0..chars.len() {
+        // `${NAME}` is left to the parameter stage
+        if !unquoted(open, '{') || (open > 0 && unquoted(open - 1, '$')) {
+            continue;
+        }
+
+        let mut depth = 0;
+        let mut commas = Vec::new();
+        let mut close = None;
+        for (i, &(c, quoting)) in chars.iter().enumerate().skip(open + 1) {
+            if quoting != Quoting::Unquoted {
+                continue;
+            }
+            match c {
+                '{' => depth += 1,
+                '}' if depth == 0 => {
+                    close = Some(i);
+                    break;
+                }
+                '}' => depth -= 1,
+                ',' if depth == 0 => commas.push(i),
+                _ => {}
+            }
+        }
+        let Some(close) = close else {
+            continue;
+        };
+
+        let alternatives = if commas.is_empty() {
+            match range(&chars[open + 1..close], limit) {
+                Some(range) => range,
+                None => continue,
+            }
+        } else {
+            let mut start = open + 1;
+            let mut alternatives = Vec::with_capacity(commas.len() + 1);
+            for end in commas.into_iter().chain([close]) {
+                alternatives.push(chars[start..end].to_vec());
+                start = end + 1;
+            }
+            alternatives
+        };
+        return Some((open, close, alternatives));
+    }
+
+    None
+}
+
+/// Expands the inside of `{1..5}`, `{a..e}` or `{0..10..2}`.
+///
+/// Stops counting just past `limit`, so a huge range can't hang the shell.
+fn range(inner: &[(char, Quoting)], limit: usize) -> Option<Vec<Braced>> {
+    if inner
+        .iter()
+        .any(|(_, quoting)| *quoting != Quoting::Unquoted)
+    {
+        return None;
+    }
+    let text: String = inner.iter().map(|(c, _)| c).collect();
+    let (from, to, step) = match text.split("..").collect::<Vec<_>>().as_slice() {
+        [from, to] => (*from, *to, 1),
+        [from, to, step] => (*from, *to, step.parse::<i64>().ok()?.unsigned_abs().max(1)),
+        _ => return None,
+    };
+
+    let items: Vec<String> = match (from.parse::<i64>(), to.parse::<i64>()) {
+        (Ok(start), Ok(end)) => {
+            // a leading zero pads every number to the same width
+            let padded = [from, to].iter().any(|n| {
+                let digits = n.trim_start_matches('-');
+                digits.len() > 1 && digits.starts_with('0')
+            });
+            let width = if padded { from.len().max(to.len()) } else { 0 };
+            sequence(start, end, step)
+                .take(limit.saturating_add(1))
+                .map(|n| format!("{:0width$}", n, width = width))
+                .collect()
+        }
+        _ => {
+            let mut from = from.chars();
+            let mut to = to.chars();
+            let (Some(start), None, Some(end), None) =
+                (from.next(), from.next(), to.next(), to.next())
+            else {
+                return None;
+            };
+            if !start.is_ascii_alphabetic() || !end.is_ascii_alphabetic() {
+                return None;
+            }
+            sequence(start as i64, end as i64, step)
+                .take(limit.saturating_add(1))
+                .filter_map(|c| char::from_u32(c as u32))
+                .map(String::from)
+                .collect()
+        }
+    };
+
+    Some(
+        items
+            .into_iter()
+            .map(|item| item.chars().map(|c| (c, Quoting::Unquoted)).collect())
+            .collect(),
+    )
+}
+
+/// Counts from `start` to `end`, both included.
+fn sequence(start: i64, end: i64, step: u64) -> impl Iterator<Item = i64> {
+    let count = (start.abs_diff(end) / step).saturating_add(1);
+    // in i128 the steps can't overflow, and every number stays between start and end
+    let step = if start <= end {
+        i128::from(step)
+    } else {
+        -i128::from(step)
+    };
+    (0..count).map(move |i| (i128::from(start) + i128::from(i) * step) as i64)
+}
+
+/// Groups the characters back into parts of the same quoting.
+fn to_word(chars: &[(char, Quoting)]) -> Word {
+    let mut word = Word::default();
+    for &(c, quoting) in chars {
+        match word.0.last_mut() {
+            Some(last) if last.quoting == quoting => last.text.push(c),
+            _ => word.0.push(Part {
+                text: c.to_string(),
+                quoting,
+            }),
+        }
+    }
+    word
+}
+
+/// Applies the tilde and parameter stages to every part of the word.
+fn expand_word(word: &Word, state: &mut State, stages: Stages) -> Result<Field, Error> {
+    let mut field = Field::new();
+
+    for (i, part) in word.0.iter().enumerate() {
+        let mut text = part.text.as_str();
+        match part.quoting {
+            Quoting::Quoted => {
+                field.extend(text.chars().map(|c| (c, Origin::Quoted)));
+                continue;
+            }
+            Quoting::Unquoted if stages.tilde && i == 0 => {
+                if let Some(rest) = tilde(text, word.0.len() == 1, state, &mut field) {
+                    text = rest;
+                }
+            }
+            _ => {}
+        }
+
+        let (literal, expanded) = match part.quoting {
+            Quoting::DoubleQuoted => (Origin::Quoted, Origin::Quoted),
+            _ => (Origin::Literal, Origin::Expanded),
+        };
+        if stages.parameter {
+            parameters(text, literal, expanded, state, &mut field)?;
+        } else {
+            field.extend(text.chars().map(|c| (c, literal)));
+        }
+    }
+
+    Ok(field)
+}
+
+/// Replaces a leading `~` with `HOME`, returning the rest of the text.
+fn tilde<'text>(
+    text: &'text str,
+    whole_word: bool,
+    state: &State,
+    field: &mut Field,
+) -> Option<&'text str> {
+    let rest = text.strip_prefix('~')?;
+    // `~user` and `~"quoted"` are left alone
+    if !(rest.starts_with('/') || (rest.is_empty() && whole_word)) {
+        return None;
+    }
+    let home = state.var("HOME")?;
+    field.extend(home.chars().map(|c| (c, Origin::Quoted)));
+    Some(rest)
+}
+
+/// Expands `$NAME`, `${NAME}`, `$?` and `$$` within the text.
+fn parameters(
+    text: &str,
+    literal: Origin,
+    expanded: Origin,
+    state: &mut State,
+    field: &mut Field,
+) -> Result<(), Error> {
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            field.push((c, literal));
+            continue;
+        }
+
+        let value = match chars.peek() {
+            Some('{') => {
+                chars.next();
+                let mut inner = String::new();
+                if !args::read_braced(&mut chars, &mut inner) {
+                    return Err(Error::BadSubstitution(format!("${{{}", inner)));
+                }
+                braced(&inner, literal, expanded, state, field)?;
+                continue;
+            }
+            Some('?' | '$' | '#') => {
+                let name = chars.next().map(String::from).unwrap_or_default();
+                lookup(&name, state)
+            }
+            Some(c) if c.is_ascii_alphabetic() || *c == '_' => {
+                let mut name = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+                    name.push(c);
+                }
+                lookup(&name, state)
+            }
+            // a lonely `$` stays as it is
+            _ => {
+                field.push(('$', literal));
+                continue;
+            }
+        };
+
+        field.extend(value.unwrap_or_default().chars().map(|c| (c, expanded)));
+    }
+
+    Ok(())
+}
+
+/// Expands the inside of `${...}`: a name, `#NAME` for the length of its
+/// value, or a name followed by one of the `-`, `=`, `+` and `?` operators
+/// and their word. With a `:` in front, the operators treat an empty value
+/// like an unset one, and anything else after the `:` takes a substring.
+fn braced(
+    inner: &str,
+    literal: Origin,
+    expanded: Origin,
+    state: &mut State,
+    field: &mut Field,
+) -> Result<(), Error> {
+    let bad = || Error::BadSubstitution(format!("${{{}}}", inner));
+
+    if let Some(name) = inner.strip_prefix('#').filter(|name| !name.is_empty()) {
+        if !is_parameter(name) {
+            return Err(bad());
+        }
+        // an unset variable has a length of 0
+        let len = lookup(name, state).map_or(0, |value| value.chars().count());
+        field.extend(len.to_string().chars().map(|c| (c, expanded)));
+        return Ok(());
+    }
+
+    let (name, rest) = inner.split_at(parameter_len(inner));
+    if name.is_empty() {
+        return Err(bad());
+    }
+    let value = lookup(name, state);
+    let (colon, rest) = match rest.strip_prefix(':') {
+        Some(rest) => (true, rest),
+        None => (false, rest),
+    };
+    let mut operator = rest.chars();
+    let op = operator.next();
+    let word = operator.as_str();
+    let missing = match &value {
+        Some(value) => colon && value.is_empty(),
+        None => true,
+    };
+
+    match op {
+        None if !colon => {}
+        // the word is split and globbed like the value it stands in for
+        Some('-') if missing => return operator_word(word, expanded, expanded, state, field),
+        Some('=') if missing => {
+            if !args::is_name(name) {
+                return Err(Error::BadSubstitution(format!("${{{}}}", inner)));
+            }
+            let mut assigned = Field::new();
+            operator_word(word, literal, expanded, state, &mut assigned)?;
+            let assigned: String = assigned.into_iter().map(|(c, _)| c).collect();
+            state.assign(name.to_string(), assigned.clone());
+            field.extend(assigned.chars().map(|c| (c, expanded)));
+            return Ok(());
+        }
+        Some('+') if missing => return Ok(()),
+        Some('+') => return operator_word(word, expanded, expanded, state, field),
+        Some('?') if missing => {
+            let mut message = Field::new();
+            operator_word(word, literal, expanded, state, &mut message)?;
+            let message = if message.is_empty() {
+                "parameter null or not set".to_string()
+            } else {
+                message.into_iter().map(|(c, _)| c).collect()
+            };
+            return Err(Error::ParameterNotSet(name.to_string(), message));
+        }
+        Some('-' | '=' | '?') => {}
+        // `${NAME:offset}` and `${NAME:offset:length}`, but not `${NAME:}`
+        _ if colon && !rest.is_empty() => {
+            let (offset, length) = match rest.split_once(':') {
+                Some((offset, length)) => (offset, Some(length)),
+                None => (rest, None),
+            };
+            // like bash, an empty offset or length counts as 0
+            let number = |n: &str| match n.trim() {
+                "" => Ok(0),
+                n => n.parse::<i64>().map_err(|_| bad()),
+            };
+            let length = length.map(number).transpose()?;
+            let value = substring(&value.unwrap_or_default(), number(offset)?, length);
+            field.extend(value.chars().map(|c| (c, expanded)));
+            return Ok(());
+        }
+        _ => return Err(bad()),
+    }
+
+    field.extend(value.unwrap_or_default().chars().map(|c| (c, expanded)));
+    Ok(())
+}
+
+/// Expands the word of an operator, like the `word` of `${NAME:-word}`, and
+/// removes its quotes.
+///
+/// Like in bash, when the whole `${...}` is within double quotes, and so
+/// `literal` is [`Origin::Quoted`], single quotes are kept as they are.
+fn operator_word(
+    word: &str,
+    literal: Origin,
+    expanded: Origin,
+    state: &mut State,
+    field: &mut Field,
+) -> Result<(), Error> {
+    let mut chars = word.chars().peekable();
+    let mut text = String::new();
+    let within_quotes = literal == Origin::Quoted;
+    let mut double_quoted = within_quotes;
+    // the text up to a quote, expanded with the origins of where it was
+    let flush = |text: &mut String, quoted: bool, state: &mut State, field: &mut Field| {
+        let (literal, expanded) = if quoted {
+            (Origin::Quoted, Origin::Quoted)
+        } else {
+            (literal, expanded)
+        };
+        parameters(&std::mem::take(text), literal, expanded, state, field)
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let escaped =
+                    chars.next_if(|c| !double_quoted || matches!(c, '\\' | '"' | '$' | '`'));
+                match escaped {
+                    Some(escaped) => {
+                        flush(&mut text, double_quoted, state, field)?;
+                        field.push((escaped, Origin::Quoted));
+                    }
+                    None => text.push(c),
+                }
+            }
+            '\'' if !double_quoted => {
+                flush(&mut text, false, state, field)?;
+                field.extend(
+                    chars
+                        .by_ref()
+                        .take_while(|c| *c != '\'')
+                        .map(|c| (c, Origin::Quoted)),
+                );
+            }
+            '"' => {
+                flush(&mut text, double_quoted, state, field)?;
+                double_quoted = within_quotes || !double_quoted;
+            }
+            // the quotes of a nested `${...}` are its own
+            '$' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push_str("${");
+                if args::read_braced(&mut chars, &mut text) {
+                    text.push('}');
+                }
+            }
+            c => text.push(c),
+        }
+    }
+    flush(&mut text, double_quoted, state, field)
+}
+
+/// The characters from `offset` on, at most `length` of them.
+///
+/// Like bash, a negative offset or length counts from the end. Anything out
+/// of bounds is clamped instead of failing.
+fn substring(value: &str, offset: i64, length: Option<i64>) -> String {
+    let len = value.chars().count() as i64;
+    let start = if offset < 0 { len + offset } else { offset };
+    if !(0..=len).contains(&start) {
+        return String::new();
+    }
+    let end = match length {
+        Some(length) if length < 0 => len + length,
+        Some(length) => start.saturating_add(length),
+        None => len,
+    }
+    .clamp(start, len);
+    value
+        .chars()
+        .skip(start as usize)
+        .take((end - start) as usize)
+        .collect()
+}
+
+/// The byte length of the parameter name the text starts with.
+fn parameter_len(text: &str) -> usize {
+    match text.as_bytes().first() {
+        Some(b'?' | b'$' | b'#') => 1,
+        Some(b'0'..=b'9') | None => 0,
+        Some(_) => text
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(text.len()),
+    }
+}
+
+fn is_parameter(name: &str) -> bool {
+    matches!(name, "?" | "$" | "#") || args::is_name(name)
+}
+
+fn lookup(name: &str, state: &State) -> Option<String> {
+    match name {
+        "?" => Some(state.last_exit_code.to_string()),
+        "$" => Some(std::process::id().to_string()),
+        // there are no positional parameters
+        "#" => Some("0".to_string()),
+        _ => state.var(name),
+    }
+}
+
+/// Splits the results of unquoted expansions on `IFS`.
+fn split(field: Field, state: &State) -> Vec<Field> {
+    let ifs = state.var("IFS").unwrap_or_else(|| " \t\n".to_string());
+
+    let mut fields = Vec::new();
+    let mut current = Field::new();
+    for (c, origin) in field {
+        if origin == Origin::Expanded && ifs.contains(c) {
+            if !current.is_empty() {
+                fields.push(std::mem::take(&mut current));
+            }
+        } else {
+            current.push((c, origin));
+        }
+    }
+    if !current.is_empty() {
+        fields.push(current);
+    }
+
+    fields
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Char(char),
+    /// `?`
+    Any,
+    /// `*`
+    Star,
+    /// `[...]`, with `!` or `^` negating it
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Token {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Self::Char(expected) => *expected == c,
+            Self::Any => true,
+            Self::Star => false,
+            Self::Class { negated, ranges } => {
+                ranges.iter().any(|(from, to)| (*from..=*to).contains(&c)) != *negated
+            }
+        }
+    }
+}
+
+fn is_pattern(field: &[(char, Origin)]) -> bool {
+    field
+        .iter()
+        .any(|(c, origin)| *origin != Origin::Quoted && matches!(c, '*' | '?' | '['))
+}
+
+fn parse_pattern(component: &[(char, Origin)]) -> Vec<Token> {
+    let mut tokens = Vec::with_capacity(component.len());
+    let mut i = 0;
+
+    while i < component.len() {
+        let (c, origin) = component[i];
+        i += 1;
+        if origin == Origin::Quoted {
+            tokens.push(Token::Char(c));
+            continue;
+        }
+        match c {
+            '?' => tokens.push(Token::Any),
+            '*' => tokens.push(Token::Star),
+            '[' => match parse_class(&component[i..]) {
+                Some((class, len)) => {
+                    tokens.push(class);
+                    i += len;
+                }
+                None => tokens.push(Token::Char('[')),
+            },
+            _ => tokens.push(Token::Char(c)),
+        }
+    }
+
+    tokens
+}
+
+/// Parses the inside of a `[...]` class, returning it and the length consumed.
+fn parse_class(rest: &[(char, Origin)]) -> Option<(Token, usize)> {
+    let mut i = 0;
+    let negated = matches!(rest.first(), Some(('!' | '^', _)));
+    if negated {
+        i += 1;
+    }
+
+    let mut ranges = Vec::new();
+    // a `]` right at the start is part of the class
+    let start = i;
+    while let Some(&(c, origin)) = rest.get(i) {
+        if c == ']' && origin != Origin::Quoted && i > start {
+            return Some((Token::Class { negated, ranges }, i + 1));
+        }
+        match (rest.get(i + 1), rest.get(i + 2)) {
+            (Some(('-', _)), Some(&(to, _))) if to != ']' => {
+                ranges.push((c, to));
+                i += 3;
+            }
+            _ => {
+                ranges.push((c, c));
+                i += 1;
+            }
+        }
+    }
+
+    None
+}
+
+fn matches(pattern: &[Token], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // where to retry when the last `*` has to match one more character
+    let mut backtrack = None;
+
+    while n < name.len() {
+        match pattern.get(p) {
+            Some(Token::Star) => {
+                backtrack = Some((p, n));
+                p += 1;
+            }
+            Some(token) if token.matches(name[n]) => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star, skipped)) => {
+                    p = star + 1;
+                    n = skipped + 1;
+                    backtrack = Some((star, skipped + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|token| *token == Token::Star)
+}
+
+/// Matches the pattern against the file system, relative to `cwd`.
+///
+/// Returns `None` once more than `limit` paths match.
+fn glob(field: &[(char, Origin)], cwd: &Path, limit: usize) -> Option<Vec<String>> {
+    let absolute = matches!(field.first(), Some(('/', _)));
+    let base = if absolute {
+        PathBuf::from("/")
+    } else {
+        cwd.to_path_buf()
+    };
+
+    let components: Vec<_> = field.split(|(c, _)| *c == '/').collect();
+    let mut found = vec![(String::new(), base)];
+
+    for (i, component) in components.iter().enumerate() {
+        let separator = if i == 0 { "" } else { "/" };
+        let last = i + 1 == components.len();
+
+        if !is_pattern(component) {
+            let name: String = component.iter().map(|(c, _)| c).collect();
+            for (display, path) in &mut found {
+                display.push_str(separator);
+                display.push_str(&name);
+                path.push(&name);
+            }
+            continue;
+        }
+
+        let pattern = parse_pattern(component);
+        // hidden files only match a pattern starting with a dot
+        let hidden = pattern.first() == Some(&Token::Char('.'));
+
+        let mut next = Vec::new();
+        for (display, path) in &found {
+            let Ok(entries) = std::fs::read_dir(path) else {
+                continue;
+            };
+            for entry in entries.filter_map(Result::ok) {
+                let Ok(name) = entry.file_name().into_string() else {
+                    continue;
+                };
+                if name.starts_with('.') && !hidden {
+                    continue;
+                }
+                let chars: Vec<_> = name.chars().collect();
+                if !matches(&pattern, &chars) || !last && !entry.path().is_dir() {
+                    continue;
+                }
+                if next.len() >= limit {
+                    return None;
+                }
+                next.push((
+                    format!("{}{}{}", display, separator, name),
+                    path.join(&name),
+                ));
+            }
+        }
+        found = next;
+    }
+
+    let mut found: Vec<_> = found
+        .into_iter()
+        .filter(|(_, path)| path.symlink_metadata().is_ok())
+        .map(|(display, _)| display)
+        .collect();
+    found.sort();
+    Some(found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn expand_line(line: &str, state: &mut State) -> Result<Vec<String>, Error> {
+        let list = args::parse_list(line).unwrap();
+        expand(&list[0][0].words, state)
+    }
+
+    #[test]
+    fn sequence_extremes() {
+        assert_eq!(
+            sequence(-i64::MAX, i64::MAX, 1 << 62).collect::<Vec<_>>(),
+            [-i64::MAX, -(1 << 62) + 1, 1, (1 << 62) + 1]
+        );
+        assert_eq!(
+            sequence(i64::MAX, i64::MIN, i64::MAX as u64).collect::<Vec<_>>(),
+            [i64::MAX, 0, -i64::MAX]
+        );
+        assert_eq!(sequence(3, -3, 3).collect::<Vec<_>>(), [3, 0, -3]);
+        let mut state = State::new();
+        assert_eq!(
+            expand_line(
+                "{-9223372036854775807..9223372036854775807..4611686018427387904}",
+                &mut state
+            )
+            .unwrap(),
+            [
+                "-9223372036854775807",
+                "-4611686018427387903",
+                "1",
+                "4611686018427387905"
+            ]
+        );
+    }
+
+    #[test]
+    fn operator_words_are_quote_removed() {
+        let mut state = State::new();
+        state.assign("SET".to_string(), "v".to_string());
+        state.assign("EMPTY".to_string(), String::new());
+        let cases: &[(&str, &[&str])] = &[
+            (r#"${SET-"a b"}"#, &["v"]),
+            (r#"${UNSET-"a b"}"#, &["a b"]),
+            (r#"${EMPTY-"a b"}"#, &[]),
+            (r#"${SET:-"a b"}"#, &["v"]),
+            (r#"${UNSET:-"a b"}"#, &["a b"]),
+            (r#"${EMPTY:-'a b'}"#, &["a b"]),
+            (r#"${SET+"a b"}"#, &["a b"]),
+            (r#"${UNSET+"a b"}"#, &[]),
+            (r#"${EMPTY+"a b"}"#, &["a b"]),
+            (r#"${SET:+'a b'}"#, &["a b"]),
+            (r#"${UNSET:+"a b"}"#, &[]),
+            (r#"${EMPTY:+"a b"}"#, &[]),
+            (r#"${SET?"a b"}"#, &["v"]),
+            (r#"${SET:?"a b"}"#, &["v"]),
+            (r#""${UNSET:-"a b"}""#, &["a b"]),
+            (r#""${UNSET:-'a b'}""#, &["'a b'"]),
+            (r#"${UNSET:-"}"}"#, &["}"]),
+            (r#"${UNSET:-\}}"#, &["}"]),
+            (r#"${UNSET:-"${ALSO_UNSET:-"c d"}"}"#, &["c d"]),
+            (r#"${UNSET:-a b}"#, &["a", "b"]),
+        ];
+        for (line, expected) in cases {
+            assert_eq!(
+                expand_line(line, &mut state).unwrap(),
+                *expected,
+                "{}",
+                line
+            );
+        }
+    }
+
+    #[test]
+    fn assign_operators_are_quote_removed() {
+        let mut state = State::new();
+        state.assign("SET".to_string(), "v".to_string());
+        state.assign("EMPTY".to_string(), String::new());
+        assert_eq!(expand_line(r#"${SET="a b"}"#, &mut state).unwrap(), ["v"]);
+        assert_eq!(expand_line(r#"${SET:="a b"}"#, &mut state).unwrap(), ["v"]);
+        assert_eq!(
+            expand_line(r#"${EMPTY="a b"}"#, &mut state).unwrap(),
+            [""; 0]
+        );
+        assert_eq!(
+            expand_line(r#""${EMPTY:="a b"}""#, &mut state).unwrap(),
+            ["a b"]
+        );
+        assert_eq!(state.var("EMPTY").as_deref(), Some("a b"));
+        assert_eq!(
+            expand_line(r#""${UNSET="c d"}""#, &mut state).unwrap(),
+            ["c d"]
+        );
+        assert_eq!(state.var("UNSET").as_deref(), Some("c d"));
+    }
+
+    #[test]
+    fn error_operators_are_quote_removed() {
+        let mut state = State::new();
+        state.assign("EMPTY".to_string(), String::new());
+        assert_eq!(
+            expand_line(r#"${UNSET?"not 'here'"}"#, &mut state),
+            Err(Error::ParameterNotSet(
+                "UNSET".to_string(),
+                "not 'here'".to_string()
+            ))
+        );
+        assert_eq!(
+            expand_line(r#"${EMPTY:?'a  b'}"#, &mut state),
+            Err(Error::ParameterNotSet(
+                "EMPTY".to_string(),
+                "a  b".to_string()
+            ))
+        );
+        assert_eq!(
+            expand_line(r#"${EMPTY?"a b"}"#, &mut state).unwrap(),
+            [""; 0]
+        );
+    }
+
+    #[test]
+    fn brace_limit() {
+        let mut state = State::new();
+        state.assign("BRACE_LIMIT".to_string(), "10".to_string());
+        assert_eq!(expand_line("{1..10}", &mut state).unwrap().len(), 10);
+        assert_eq!(
+            expand_line("{1..11}", &mut state),
+            Err(Error::TooManyWords("{1..11}".to_string(), 10))
+        );
+        assert_eq!(
+            expand_line("{a,b,c}{1..5}", &mut state),
+            Err(Error::TooManyWords("{a,b,c}{1..5}".to_string(), 10))
+        );
+    }
+
+    #[test]
+    fn brace_limit_default() {
+        let mut state = State::new();
+        let error = expand_line("{0..100000}", &mut state).unwrap_err();
+        assert_eq!(
+            error,
+            Error::TooManyWords("{0..100000}".to_string(), DEFAULT_BRACE_LIMIT)
+        );
+        assert_eq!(
+            error.to_string(),
+            "{0..100000}: brace expansion produces more than 100000 words"
+        );
+    }
+
+    #[test]
+    fn glob_limit() {
+        let dir = std::env::temp_dir().join(format!("expand-glob-limit-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["a", "b", "c"] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+        let mut state = State::new();
+        state.path = dir.clone();
+
+        assert_eq!(expand_line("*", &mut state).unwrap(), ["a", "b", "c"]);
+        state.assign("GLOB_LIMIT".to_string(), "2".to_string());
+        assert_eq!(
+            expand_line("*", &mut state),
+            Err(Error::TooManyMatches("*".to_string(), 2))
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    fn expand_stages(line: &str, state: &mut State, stages: Stages) -> Vec<String> {
+        let list = args::parse_list(line).unwrap();
+        expand_with(&list[0][0].words, state, stages).unwrap()
+    }
+
+    /// A directory with the files `a.txt`, `b.txt` and `c.rs`, removed on drop.
+    struct Files(PathBuf);
+
+    impl Files {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("expand-{}-{}", name, std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            for file in ["a.txt", "b.txt", "c.rs"] {
+                std::fs::write(dir.join(file), "").unwrap();
+            }
+            Self(dir)
+        }
+    }
+
+    impl Drop for Files {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn braces_come_first() {
+        let mut state = State::new();
+        assert_eq!(expand_line("a{b,c}d", &mut state).unwrap(), ["abd", "acd"]);
+        assert_eq!(expand_line("{1..3}", &mut state).unwrap(), ["1", "2", "3"]);
+        assert_eq!(
+            expand_line("{05..1..2}", &mut state).unwrap(),
+            ["05", "03", "01"]
+        );
+        assert_eq!(expand_line("{c..a}", &mut state).unwrap(), ["c", "b", "a"]);
+        assert_eq!(expand_line("{a}", &mut state).unwrap(), ["{a}"]);
+        assert_eq!(expand_line("'{a,b}'", &mut state).unwrap(), ["{a,b}"]);
+        // an expanded value is never brace expanded
+        state.assign("X".to_string(), "{a,b}".to_string());
+        assert_eq!(expand_line("$X", &mut state).unwrap(), ["{a,b}"]);
+        // but the expansions within the words are
+        state.assign("Y".to_string(), "y".to_string());
+        assert_eq!(expand_line("{$Y,z}", &mut state).unwrap(), ["y", "z"]);
+    }
+
+    #[test]
+    fn tilde_only_when_unquoted_and_leading() {
+        let mut state = State::new();
+        state.assign("HOME".to_string(), "/home/me".to_string());
+        assert_eq!(expand_line("~", &mut state).unwrap(), ["/home/me"]);
+        assert_eq!(expand_line("~/a", &mut state).unwrap(), ["/home/me/a"]);
+        assert_eq!(expand_line("a~", &mut state).unwrap(), ["a~"]);
+        assert_eq!(expand_line("\"~\"", &mut state).unwrap(), ["~"]);
+        assert_eq!(expand_line("~user", &mut state).unwrap(), ["~user"]);
+        state.assign("X".to_string(), "~".to_string());
+        assert_eq!(expand_line("$X", &mut state).unwrap(), ["~"]);
+    }
+
+    #[test]
+    fn parameters() {
+        let mut state = State::new();
+        state.last_exit_code = 3;
+        state.assign("X".to_string(), "value".to_string());
+        assert_eq!(expand_line("$?", &mut state).unwrap(), ["3"]);
+        assert_eq!(
+            expand_line("$X.${X}_$UNSET", &mut state).unwrap(),
+            ["value.value_"]
+        );
+        assert_eq!(expand_line("'$X'", &mut state).unwrap(), ["$X"]);
+        assert_eq!(expand_line("\"$X\"", &mut state).unwrap(), ["value"]);
+        assert_eq!(
+            expand_line("${#X} ${#UNSET}", &mut state).unwrap(),
+            ["5", "0"]
+        );
+        assert_eq!(expand_line("$", &mut state).unwrap(), ["$"]);
+        // the value is not expanded again
+        state.assign("Y".to_string(), "$X".to_string());
+        assert_eq!(expand_line("$Y", &mut state).unwrap(), ["$X"]);
+        assert_eq!(
+            expand_line("${X", &mut state),
+            Err(Error::BadSubstitution("${X".to_string()))
+        );
+        assert_eq!(
+            expand_line("${1X}", &mut state),
+            Err(Error::BadSubstitution("${1X}".to_string()))
+        );
+    }
+
+    #[test]
+    fn globs_after_splitting() {
+        let files = Files::new("globs");
+        let mut state = State::new();
+        state.path = files.0.clone();
+        assert_eq!(
+            expand_line("*.txt", &mut state).unwrap(),
+            ["a.txt", "b.txt"]
+        );
+        assert_eq!(expand_line("?.rs", &mut state).unwrap(), ["c.rs"]);
+        assert_eq!(
+            expand_line("[ab].*", &mut state).unwrap(),
+            ["a.txt", "b.txt"]
+        );
+        assert_eq!(expand_line("'*.txt'", &mut state).unwrap(), ["*.txt"]);
+        assert_eq!(expand_line("*.none", &mut state).unwrap(), ["*.none"]);
+        // a pattern from an unquoted expansion is globbed, a quoted one is not
+        state.assign("X".to_string(), "*.rs".to_string());
+        assert_eq!(expand_line("$X", &mut state).unwrap(), ["c.rs"]);
+        assert_eq!(expand_line("\"$X\"", &mut state).unwrap(), ["*.rs"]);
+        // every field of the split is globbed on its own
+        state.assign("X".to_string(), "a.* c.*".to_string());
+        assert_eq!(expand_line("$X", &mut state).unwrap(), ["a.txt", "c.rs"]);
+        let absolute = format!("{}/c.*", files.0.display());
+        assert_eq!(
+            expand_line(&absolute, &mut state).unwrap(),
+            [format!("{}/c.rs", files.0.display())]
+        );
+    }
+
+    #[test]
+    fn stages_can_be_turned_off() {
+        let files = Files::new("stages");
+        let mut state = State::new();
+        state.path = files.0.clone();
+        state.assign("X".to_string(), "a b".to_string());
+        let none = Stages {
+            brace: false,
+            tilde: false,
+            parameter: false,
+            split: false,
+            glob: false,
+        };
+        assert_eq!(
+            expand_stages("{1,2} ~ $X *.rs", &mut state, none),
+            ["{1,2}", "~", "$X", "*.rs"]
+        );
+        let no_split = Stages {
+            split: false,
+            ..Stages::default()
+        };
+        assert_eq!(expand_stages("$X", &mut state, no_split), ["a b"]);
+        let no_glob = Stages {
+            glob: false,
+            ..Stages::default()
+        };
+        assert_eq!(expand_stages("*.rs", &mut state, no_glob), ["*.rs"]);
+
+        // like an assignment, neither braces, splitting nor globs
+        let value = &args::parse_list("{1,2}$X*.rs").unwrap()[0][0].words[0];
+        assert_eq!(expand_value(value, &mut state).unwrap(), "{1,2}a b*.rs");
+    }
+
+    #[test]
+    fn only_expansions_are_split() {
+        let mut state = State::new();
+        state.assign("X".to_string(), " a  b ".to_string());
+        assert_eq!(expand_line("$X", &mut state).unwrap(), ["a", "b"]);
+        assert_eq!(expand_line("\"$X\"", &mut state).unwrap(), [" a  b "]);
+        assert_eq!(
+            expand_line("x${X}y", &mut state).unwrap(),
+            ["x", "a", "b", "y"]
+        );
+        assert_eq!(expand_line("\"\"", &mut state).unwrap(), [""]);
+        assert_eq!(
+            expand_line("$UNSET", &mut state).unwrap(),
+            Vec::<String>::new()
+        );
+        state.assign("IFS".to_string(), ":".to_string());
+        assert_eq!(expand_line("$X", &mut state).unwrap(), [" a  b "]);
+        state.assign("X".to_string(), "a:b".to_string());
+        assert_eq!(expand_line("$X", &mut state).unwrap(), ["a", "b"]);
+        // the fields are neither split nor expanded a second time
+        state.assign("Y".to_string(), "$X".to_string());
+        assert_eq!(expand_line("$Y", &mut state).unwrap(), ["$X"]);
+    }
+
+    #[test]
+    fn sequences() {
+        assert_eq!(sequence(1, 5, 2).collect::<Vec<_>>(), [1, 3, 5]);
+        assert_eq!(sequence(1, 6, 2).collect::<Vec<_>>(), [1, 3, 5]);
+        assert_eq!(sequence(5, 1, 1).collect::<Vec<_>>(), [5, 4, 3, 2, 1]);
+        assert_eq!(sequence(-1, 1, 1).collect::<Vec<_>>(), [-1, 0, 1]);
+        assert_eq!(sequence(7, 7, 3).collect::<Vec<_>>(), [7]);
+
+        let mut state = State::new();
+        assert_eq!(
+            expand_line("{a,b,c} x{1..3}", &mut state).unwrap(),
+            ["a", "b", "c", "x1", "x2", "x3"]
+        );
+        assert_eq!(
+            expand_line("{a,{1..2}}{x,}", &mut state).unwrap(),
+            ["ax", "a", "1x", "1", "2x", "2"]
+        );
+        assert_eq!(
+            expand_line("{-1..-3}", &mut state).unwrap(),
+            ["-1", "-2", "-3"]
+        );
+        assert_eq!(
+            expand_line("{1..a} x{,}", &mut state).unwrap(),
+            ["{1..a}", "x", "x"]
+        );
+    }
+
+    #[test]
+    fn substrings() {
+        assert_eq!(substring("hello", 1, None), "ello");
+        assert_eq!(substring("hello", 1, Some(3)), "ell");
+        assert_eq!(substring("hello", -3, None), "llo");
+        assert_eq!(substring("hello", -3, Some(-1)), "ll");
+        assert_eq!(substring("hello", 1, Some(-1)), "ell");
+        assert_eq!(substring("hello", 5, None), "");
+        assert_eq!(substring("hello", 6, None), "");
+        assert_eq!(substring("hello", -6, None), "");
+        assert_eq!(substring("hello", 2, Some(100)), "llo");
+        assert_eq!(substring("hello", 3, Some(-4)), "");
+        assert_eq!(substring("héllo", 1, Some(2)), "él");
+        assert_eq!(substring("hello", i64::MIN, Some(i64::MAX)), "");
+        assert_eq!(substring("hello", 0, Some(i64::MIN)), "");
+
+        let mut state = State::new();
+        state.assign("X".to_string(), "hello".to_string());
+        assert_eq!(expand_line("${X:1:3}", &mut state).unwrap(), ["ell"]);
+        assert_eq!(expand_line("${X: -2}", &mut state).unwrap(), ["lo"]);
+        assert_eq!(expand_line("[${X:1:}]", &mut state).unwrap(), ["[]"]);
+        assert_eq!(expand_line("${X::2}", &mut state).unwrap(), ["he"]);
+        assert_eq!(expand_line("${X: }", &mut state).unwrap(), ["hello"]);
+        assert_eq!(expand_line("${#X}", &mut state).unwrap(), ["5"]);
+        assert_eq!(
+            expand_line("${X:a}", &mut state),
+            Err(Error::BadSubstitution("${X:a}".to_string()))
+        );
+        assert_eq!(
+            expand_line("${X:1:b}", &mut state),
+            Err(Error::BadSubstitution("${X:1:b}".to_string()))
+        );
+        assert_eq!(
+            expand_line("${X:}", &mut state),
+            Err(Error::BadSubstitution("${X:}".to_string()))
+        );
+    }
+}
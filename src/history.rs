@@ -0,0 +1,569 @@
+use std::{
+    ffi::CString,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::sys;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("{0}: event not found")]
+    EventNotFound(String),
+}
+
+/// Used when `HISTSIZE` is not set or not a valid number.
+pub const DEFAULT_HISTSIZE: usize = 500;
+
+/// The file named by `HISTFILE`, unset or empty disables it.
+pub fn histfile() -> Option<PathBuf> {
+    std::env::var_os("HISTFILE")
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Which lines should be kept out of the history, parsed from `HISTCONTROL`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct HistControl {
+    pub ignoredups: bool,
+    pub ignorespace: bool,
+    /// Not in bash, keeps lines that fail to parse out of the history.
+    pub ignoreinvalid: bool,
+}
+
+impl From<&str> for HistControl {
+    fn from(value: &str) -> Self {
+        let mut control = Self::default();
+        for option in value.split(':') {
+            match option {
+                "ignoredups" => control.ignoredups = true,
+                "ignorespace" => control.ignorespace = true,
+                "ignoreinvalid" => control.ignoreinvalid = true,
+                "ignoreboth" => {
+                    control.ignoredups = true;
+                    control.ignorespace = true;
+                }
+                _ => {}
+            }
+        }
+        control
+    }
+}
+
+/// The commands entered during this session, oldest first.
+pub struct History {
+    pub history: Vec<String>,
+    /// When each entry was added, in seconds since the epoch, parallel to `history`.
+    ///
+    /// Entries read from a file without a `#<epoch>` line have none.
+    times: Vec<Option<i64>>,
+    /// How many entries were dropped from the front, so the numbers shown
+    /// keep counting up like in bash instead of starting over at 1.
+    base: usize,
+    max_len: usize,
+    control: HistControl,
+    /// Where [`History::save`] writes to, set by [`History::read_file`].
+    file: Option<PathBuf>,
+}
+
+impl History {
+    pub fn new(max_len: usize, control: HistControl) -> Self {
+        Self {
+            history: Vec::with_capacity(max_len.min(100)),
+            times: Vec::with_capacity(max_len.min(100)),
+            base: 0,
+            max_len,
+            control,
+            file: None,
+        }
+    }
+
+    /// Reads the maximum length from `HISTSIZE` and the filters from `HISTCONTROL`.
+    pub fn from_env() -> Self {
+        let max_len = std::env::var("HISTSIZE")
+            .ok()
+            .and_then(|size| size.parse().ok())
+            .unwrap_or(DEFAULT_HISTSIZE);
+        let control = std::env::var("HISTCONTROL")
+            .map(|control| HistControl::from(control.as_str()))
+            .unwrap_or_default();
+        Self::new(max_len, control)
+    }
+
+    /// Appends the lines of a history file, like bash does on startup, and
+    /// remembers it for [`History::save`].
+    ///
+    /// Fails on anything but a regular file, so a `HISTFILE` pointing at a
+    /// directory is neither read nor written. A missing file is created by
+    /// [`History::save`].
+    pub fn read_file(&mut self, path: &Path) -> io::Result<()> {
+        match std::fs::metadata(path) {
+            Ok(metadata) if !metadata.is_file() => return Err(io::Error::other("Is not a file")),
+            Ok(_) => self.file = Some(path.to_path_buf()),
+            // created on exit
+            Err(e) => {
+                if e.kind() == io::ErrorKind::NotFound {
+                    self.file = Some(path.to_path_buf());
+                }
+                return Err(e);
+            }
+        }
+        let content = std::fs::read(path)?;
+        let mut time = None;
+        for line in String::from_utf8_lossy(&content).lines() {
+            // bash writes the time of an entry as a `#<epoch>` line before it
+            if let Some(epoch) = line.strip_prefix('#').and_then(|e| e.parse().ok()) {
+                time = Some(epoch);
+            } else if !line.trim().is_empty() {
+                self.push_at(line.to_string(), time.take());
+            }
+        }
+        Ok(())
+    }
+
+    /// Overwrites the file the history was read from with the whole history,
+    /// like bash does on exit.
+    ///
+    /// Like bash, the times are only kept when `timestamps`, meaning
+    /// `HISTTIMEFORMAT` is set.
+    pub fn save(&self, timestamps: bool) -> io::Result<()> {
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+        let mut content = String::new();
+        for (line, time) in self.history.iter().zip(&self.times) {
+            if let Some(time) = time.filter(|_| timestamps) {
+                content.push_str(&format!("#{}\n", time));
+            }
+            content.push_str(line);
+            content.push('\n');
+        }
+        std::fs::write(path, content)
+    }
+
+    /// Records a line as it was typed, unless `HISTCONTROL` filters it out.
+    pub fn add(&mut self, line: &str) {
+        if self.control.ignorespace && line.starts_with(' ') {
+            return;
+        }
+        let line = line.trim();
+        if self.control.ignoredups && self.history.last().is_some_and(|last| last == line) {
+            return;
+        }
+        self.push(line.to_string());
+    }
+
+    /// Records a line that failed to parse, like bash does, unless
+    /// `HISTCONTROL` has `ignoreinvalid`.
+    pub fn add_invalid(&mut self, line: &str) {
+        if !self.control.ignoreinvalid {
+            self.add(line);
+        }
+    }
+
+    /// Adds a line, dropping the oldest entries once `max_len` is exceeded.
+    pub fn push(&mut self, line: String) {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .and_then(|now| i64::try_from(now.as_secs()).ok());
+        self.push_at(line, now);
+    }
+
+    fn push_at(&mut self, line: String, time: Option<i64>) {
+        self.history.push(line);
+        self.times.push(time);
+        if self.history.len() > self.max_len {
+            let overflow = self.history.len() - self.max_len;
+            self.history.drain(..overflow);
+            self.times.drain(..overflow);
+            self.base += overflow;
+        }
+    }
+
+    /// Rewrites the `!!`, `!n`, `!-n` and `!prefix` events in `line` against the history.
+    ///
+    /// Returns `None` when the line does not contain any event, so the caller
+    /// knows when to echo the expanded line.
+    pub fn expand(&self, line: &str) -> Result<Option<String>, Error> {
+        let mut expanded = String::with_capacity(line.len());
+        let mut changed = false;
+        let mut single_quoted = false;
+        let mut double_quoted = false;
+
+        let mut chars = line.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '\\' if !single_quoted => {
+                    expanded.push(c);
+                    if let Some((_, next)) = chars.next() {
+                        expanded.push(next);
+                    }
+                    continue;
+                }
+                '\'' if !double_quoted => single_quoted = !single_quoted,
+                '"' if !single_quoted => double_quoted = !double_quoted,
+                '!' if !single_quoted => {
+                    let event = &line[i + 1..];
+                    if let Some((entry, len)) = self.event(event)? {
+                        expanded.push_str(entry);
+                        changed = true;
+                        // skip the rest of the event designator
+                        for _ in event[..len].chars() {
+                            chars.next();
+                        }
+                        continue;
+                    }
+                }
+                _ => {}
+            }
+            expanded.push(c);
+        }
+
+        Ok(changed.then_some(expanded))
+    }
+
+    /// Looks up the entry for the event following a `!`, together with the
+    /// byte length of the event designator.
+    fn event(&self, event: &str) -> Result<Option<(&str, usize)>, Error> {
+        let len = event
+            .find(|c: char| c.is_whitespace() || "!;&|<>()\"'".contains(c))
+            .unwrap_or(event.len());
+
+        let (entry, len) = match event.as_bytes().first() {
+            Some(b'!') => (self.history.last(), 1),
+            // a `!` followed by a blank or an operator is not an event
+            _ if len == 0 => return Ok(None),
+            None | Some(b'=') => return Ok(None),
+            Some(b'-' | b'0'..=b'9') => {
+                let designator = &event[..len];
+                let entry = match designator.strip_prefix('-') {
+                    Some(back) => back
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|back| self.history.len().checked_sub(back)),
+                    None => designator
+                        .parse::<usize>()
+                        .ok()
+                        .and_then(|offset| self.index(offset)),
+                };
+                (entry.and_then(|i| self.history.get(i)), len)
+            }
+            Some(_) => {
+                let prefix = &event[..len];
+                let entry = self.history.iter().rev().find(|l| l.starts_with(prefix));
+                (entry, len)
+            }
+        };
+
+        match entry {
+            Some(entry) => Ok(Some((entry, len))),
+            None => Err(Error::EventNotFound(format!("!{}", &event[..len]))),
+        }
+    }
+
+    /// The index into `history` of the entry numbered `offset` by `history`.
+    fn index(&self, offset: usize) -> Option<usize> {
+        offset
+            .checked_sub(self.base + 1)
+            .filter(|i| *i < self.history.len())
+    }
+
+    /// Removes the entry at the 1-based `offset`, as shown by `history`.
+    pub fn delete(&mut self, offset: usize) -> Option<String> {
+        let index = self.index(offset)?;
+        self.times.remove(index);
+        Some(self.history.remove(index))
+    }
+
+    /// Resolves an `fc` history specification to an index into the history.
+    ///
+    /// Like bash, the line running `fc` is already the last entry and is not
+    /// counted: `-1` is the command before it, and a positive number is the
+    /// 1-based offset shown by `history`. Anything else finds the latest
+    /// command starting with it.
+    pub fn find(&self, spec: &str) -> Option<usize> {
+        let len = self.history.len().checked_sub(1)?;
+        let index = match spec.parse::<isize>() {
+            Ok(back) if back < 0 => len.checked_sub(back.unsigned_abs())?,
+            Ok(offset) => self.index(offset.unsigned_abs())?,
+            Err(_) => self.history[..len]
+                .iter()
+                .rposition(|l| l.starts_with(spec))?,
+        };
+        (index < len).then_some(index)
+    }
+
+    /// The index of the oldest entry before the line running `fc`.
+    pub fn oldest(&self) -> Option<usize> {
+        (self.history.len() > 1).then_some(0)
+    }
+
+    /// Writes the entries from `first` to `last`, both 0-based and inclusive,
+    /// together with their 1-based offset.
+    pub fn print_range(&self, first: usize, last: usize, out: &mut dyn Write) -> io::Result<()> {
+        for (i, line) in self.history.iter().enumerate().take(last + 1).skip(first) {
+            writeln!(out, "{}\t {}", self.base + i + 1, line)?;
+        }
+        Ok(())
+    }
+
+    /// Writes the last `count` entries together with their 1-based offset,
+    /// and their time formatted by `format`, the value of `HISTTIMEFORMAT`.
+    pub fn print(&self, count: usize, format: Option<&str>, out: &mut dyn Write) -> io::Result<()> {
+        let start = self.history.len().saturating_sub(count);
+        for (i, (line, time)) in self.history.iter().zip(&self.times).enumerate().skip(start) {
+            // bash shows entries without a time as `??`
+            let time = match (format, time) {
+                (Some(format), Some(time)) => format_time(*time, format),
+                (Some(_), None) => "?? ".to_string(),
+                (None, _) => String::new(),
+            };
+            writeln!(out, "{:>5}  {}{}", self.base + i + 1, time, line)?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats the seconds since the epoch in local time with `strftime`, like
+/// bash does for `HISTTIMEFORMAT`.
+fn format_time(time: i64, format: &str) -> String {
+    let Ok(format) = CString::new(format) else {
+        return String::new();
+    };
+    // past 2038 where time_t is 32 bits
+    let Some(time) = sys::time_t::try_from(time).ok() else {
+        return String::new();
+    };
+    let mut tm = sys::Tm::default();
+    let mut buf = [0u8; 256];
+    // SAFETY: both calls only write into the buffers passed, which outlive them
+    let len = unsafe {
+        if sys::localtime_r(&time, &mut tm).is_null() {
+            return String::new();
+        }
+        sys::strftime(buf.as_mut_ptr().cast(), buf.len(), format.as_ptr(), &tm)
+    };
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(DEFAULT_HISTSIZE, HistControl::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(max_len: usize, lines: &[&str]) -> History {
+        let mut history = History::new(max_len, HistControl::default());
+        for line in lines {
+            history.push(line.to_string());
+        }
+        history
+    }
+
+    #[test]
+    fn delete() {
+        let mut history = history(10, &["a", "b", "c"]);
+        assert_eq!(history.delete(2), Some("b".to_string()));
+        assert_eq!(history.history, ["a", "c"]);
+        assert_eq!(history.times.len(), 2);
+        assert_eq!(history.delete(0), None);
+        assert_eq!(history.delete(3), None);
+        assert_eq!(history.delete(2), Some("c".to_string()));
+        assert_eq!(history.history, ["a"]);
+    }
+
+    #[test]
+    fn trims_the_oldest() {
+        let trimmed = history(3, &["a", "b", "c", "d", "e"]);
+        assert_eq!(trimmed.history, ["c", "d", "e"]);
+        assert_eq!(trimmed.times.len(), 3);
+
+        let empty = history(0, &["a"]);
+        assert!(empty.history.is_empty());
+    }
+
+    #[test]
+    fn control() {
+        let mut history = History::new(10, HistControl::from("ignoreboth"));
+        history.add("ls");
+        history.add("ls");
+        history.add(" secret");
+        history.add("pwd");
+        history.add("ls");
+        assert_eq!(history.history, ["ls", "pwd", "ls"]);
+
+        let mut history = History::new(10, HistControl::from("ignoredups"));
+        history.add(" ls");
+        history.add("ls");
+        assert_eq!(history.history, ["ls"]);
+
+        let mut history = History::new(10, HistControl::default());
+        history.add("ls");
+        history.add("ls");
+        history.add(" ls");
+        assert_eq!(history.history, ["ls", "ls", "ls"]);
+    }
+
+    #[test]
+    fn events() {
+        let history = history(10, &["echo one", "ls -l", "echo two"]);
+        assert_eq!(history.expand("!!").unwrap().as_deref(), Some("echo two"));
+        assert_eq!(
+            history.expand("!1 x").unwrap().as_deref(),
+            Some("echo one x")
+        );
+        assert_eq!(history.expand("!-2").unwrap().as_deref(), Some("ls -l"));
+        assert_eq!(
+            history.expand("!ls|wc").unwrap().as_deref(),
+            Some("ls -l|wc")
+        );
+        assert_eq!(history.expand("echo hi").unwrap(), None);
+        assert_eq!(history.expand("echo '!!' \\!! ! !=").unwrap(), None);
+        assert_eq!(
+            history.expand("!nope").unwrap_err().to_string(),
+            "!nope: event not found"
+        );
+        assert!(history.expand("!9").is_err());
+    }
+
+    #[test]
+    fn invalid_lines() {
+        let mut history = History::new(10, HistControl::default());
+        history.add_invalid("echo 'x");
+        history.add("ls");
+        assert_eq!(history.history, ["echo 'x", "ls"]);
+
+        let mut history = History::new(10, HistControl::from("ignoreinvalid:ignoredups"));
+        history.add_invalid("echo 'x");
+        history.add("ls");
+        history.add("ls");
+        assert_eq!(history.history, ["ls"]);
+    }
+
+    #[test]
+    fn find_skips_the_running_fc() {
+        let history = history(3, &["echo a", "ls", "echo b", "fc -l"]);
+        // `echo a` was trimmed, 2 is `ls`
+        assert_eq!(history.find("-1"), Some(1));
+        assert_eq!(history.find("-2"), Some(0));
+        assert_eq!(history.find("-3"), None);
+        assert_eq!(history.find("2"), Some(0));
+        assert_eq!(history.find("3"), Some(1));
+        assert_eq!(history.find("1"), None);
+        // the line running `fc` can't be found
+        assert_eq!(history.find("4"), None);
+        assert_eq!(history.find("fc"), None);
+        assert_eq!(history.find("ec"), Some(1));
+        assert_eq!(history.find("l"), Some(0));
+        assert_eq!(history.oldest(), Some(0));
+
+        let history = History::default();
+        assert_eq!(history.find("-1"), None);
+        assert_eq!(history.oldest(), None);
+    }
+
+    #[test]
+    fn read_file() {
+        let path = std::env::temp_dir().join(format!("history-read-{}", std::process::id()));
+        std::fs::write(&path, "first\n\n  \nsecond\n").unwrap();
+
+        let mut history = history(10, &["typed"]);
+        history.read_file(&path).unwrap();
+        assert_eq!(history.history, ["typed", "first", "second"]);
+        assert_eq!(history.file.as_ref(), Some(&path));
+        std::fs::remove_file(&path).unwrap();
+
+        // a missing file is remembered, to be created later, a directory is not
+        let mut history = History::default();
+        assert!(history.read_file(&path).is_err());
+        assert_eq!(history.file.as_ref(), Some(&path));
+        let mut history = History::default();
+        assert!(history.read_file(&std::env::temp_dir()).is_err());
+        assert_eq!(history.file, None);
+    }
+
+    #[test]
+    fn save() {
+        let path = std::env::temp_dir().join(format!("history-save-{}", std::process::id()));
+
+        // nothing to write to
+        history(10, &["a"]).save(false).unwrap();
+
+        let mut history = History::default();
+        assert!(history.read_file(&path).is_err());
+        history.push("new".to_string());
+        history.save(false).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "new\n");
+
+        let mut history = History::new(2, HistControl::default());
+        history.read_file(&path).unwrap();
+        history.push("newer".to_string());
+        history.push("newest".to_string());
+        history.save(false).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "newer\nnewest\n");
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn timestamps() {
+        let path = std::env::temp_dir().join(format!("history-times-{}", std::process::id()));
+        std::fs::write(&path, "#100\nfirst\nsecond\n#not a time\n").unwrap();
+
+        let mut history = History::new(10, HistControl::default());
+        history.read_file(&path).unwrap();
+        assert_eq!(history.history, ["first", "second", "#not a time"]);
+        assert_eq!(history.times, [Some(100), None, None]);
+
+        history.save(true).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "#100\nfirst\nsecond\n#not a time\n"
+        );
+        history.save(false).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "first\nsecond\n#not a time\n"
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        let mut out = Vec::new();
+        history.print(3, Some("[%Y] "), &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.starts_with("    1  [19"), "{}", out);
+        assert!(out.ends_with("    3  ?? #not a time\n"), "{}", out);
+        assert_eq!(format_time(0, ""), "");
+        assert_eq!(format_time(i64::MAX, "%Y"), "");
+    }
+
+    fn printed(history: &History, count: usize) -> String {
+        let mut out = Vec::new();
+        history.print(count, None, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn numbers_keep_counting() {
+        let mut history = history(3, &["a", "b", "c", "d", "e"]);
+        assert_eq!(printed(&history, 2), "    4  d\n    5  e\n");
+        assert_eq!(history.index(2), None);
+        assert_eq!(history.index(3), Some(0));
+        assert_eq!(history.index(5), Some(2));
+        assert_eq!(history.index(6), None);
+
+        assert_eq!(history.delete(4), Some("d".to_string()));
+        assert_eq!(printed(&history, 10), "    3  c\n    4  e\n");
+
+        // lines read from a file count on from the ones before
+        let path = std::env::temp_dir().join(format!("history-numbers-{}", std::process::id()));
+        std::fs::write(&path, "f\ng\n").unwrap();
+        history.read_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(printed(&history, 10), "    4  e\n    5  f\n    6  g\n");
+    }
+}
@@ -0,0 +1,1558 @@
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::HashMap,
+    ffi::CString,
+    io::{self, BufRead, IsTerminal, Read, Write},
+    os::{
+        fd::AsFd,
+        unix::{
+            ffi::OsStrExt,
+            fs::OpenOptionsExt,
+            process::{CommandExt, ExitStatusExt},
+        },
+    },
+    path::{Component, Path, PathBuf},
+    process::{Child, ChildStdout, Command, Stdio},
+    str::FromStr,
+};
+
+mod args;
+mod completion;
+mod expand;
+mod history;
+mod prompt;
+mod redirect;
+mod signal;
+mod sys;
+
+pub use completion::{Completion, Entry, EntryKind};
+use history::History;
+use redirect::Redirects;
+
+pub type ExitCode = i32;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Errors {
+    #[error("{0}: command not found")]
+    CommandNotFound(String),
+    #[error("The command {0} is missing an argument")]
+    MissingArgument(&'static str),
+    #[error("The incorrect argument {0} should be a {1}")]
+    IncorrectArgumentType(String, &'static str),
+    #[error("Path is not valid {0}")]
+    IncorrectArgument(String),
+    #[error("{0}: {}", redirect::reason(.1))]
+    Spawn(String, io::Error),
+    #[error("set: {0}: invalid option name")]
+    InvalidOption(String),
+    #[error("{0}: HOME not set")]
+    HomeNotSet(&'static str),
+    #[error("history: {0}: history position out of range")]
+    HistoryOutOfRange(String),
+    #[error("fc: history specification out of range")]
+    FcOutOfRange,
+    #[error("{0}")]
+    Args(#[from] args::Error),
+    #[error("{0}")]
+    Expand(#[from] expand::Error),
+    #[error("{0}")]
+    Redirect(#[from] redirect::Error),
+    #[error("Io Error {0}")]
+    IoError(#[from] io::Error),
+}
+
+impl Errors {
+    /// The status `$?` reports after the error, following bash.
+    pub fn exit_code(&self) -> ExitCode {
+        match self {
+            Self::CommandNotFound(_) => 127,
+            Self::Spawn(_, e) if e.kind() == io::ErrorKind::NotFound => 127,
+            // found, but could not be executed
+            Self::Spawn(..) => 126,
+            // like bash, a usage error of a builtin or the shell
+            Self::Args(_) | Self::InvalidOption(_) => 2,
+            _ => 1,
+        }
+    }
+}
+
+enum Builtins {
+    Exit,
+    Echo,
+    Type,
+    Pwd,
+    Cd,
+    History,
+    Compgen,
+    Set,
+    Fc,
+}
+
+impl Builtins {
+    const NAMES: [&'static str; 9] = [
+        "exit", "echo", "type", "pwd", "cd", "history", "compgen", "set", "fc",
+    ];
+}
+
+impl TryFrom<&str> for Builtins {
+    type Error = Errors;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "exit" => Ok(Self::Exit),
+            "echo" => Ok(Self::Echo),
+            "type" => Ok(Self::Type),
+            "pwd" => Ok(Self::Pwd),
+            "cd" => Ok(Self::Cd),
+            "history" => Ok(Self::History),
+            "compgen" => Ok(Self::Compgen),
+            "set" => Ok(Self::Set),
+            "fc" => Ok(Self::Fc),
+            _ => Err(Errors::CommandNotFound(value.to_string())),
+        }
+    }
+}
+
+/// The toggles bash exposes through `set -o`.
+#[derive(Debug, Default)]
+struct ShellOptions {
+    /// Require `IGNOREEOF` consecutive Ctrl+D before leaving an interactive shell.
+    ignoreeof: bool,
+    /// Print the new working directory after every successful `cd`.
+    cdprint: bool,
+    /// Refuse to overwrite existing files with `>`, `>|` still does.
+    noclobber: bool,
+    /// Stop a script at the first command that fails.
+    errexit: bool,
+    /// A pipeline fails with its rightmost failing command, not only the last.
+    pipefail: bool,
+}
+
+impl ShellOptions {
+    fn from_env() -> Self {
+        // bash enables the options listed in an inherited SHELLOPTS
+        let shellopts = std::env::var("SHELLOPTS").unwrap_or_default();
+        let enabled = |name| shellopts.split(':').any(|option| option == name);
+
+        Self {
+            ignoreeof: std::env::var_os("IGNOREEOF").is_some() || enabled("ignoreeof"),
+            cdprint: false,
+            noclobber: enabled("noclobber"),
+            errexit: enabled("errexit"),
+            pipefail: enabled("pipefail"),
+        }
+    }
+
+    /// Every option with its state, sorted by name like `set -o` lists them.
+    fn list(&self) -> [(&'static str, bool); 5] {
+        [
+            ("cdprint", self.cdprint),
+            ("errexit", self.errexit),
+            ("ignoreeof", self.ignoreeof),
+            ("noclobber", self.noclobber),
+            ("pipefail", self.pipefail),
+        ]
+    }
+
+    fn get_mut(&mut self, name: &str) -> Option<&mut bool> {
+        match name {
+            "cdprint" => Some(&mut self.cdprint),
+            "errexit" => Some(&mut self.errexit),
+            "ignoreeof" => Some(&mut self.ignoreeof),
+            "noclobber" => Some(&mut self.noclobber),
+            "pipefail" => Some(&mut self.pipefail),
+            _ => None,
+        }
+    }
+}
+
+/// Whether the user may search the directory, asking the kernel like `chdir` would.
+fn searchable(dir: &Path) -> io::Result<()> {
+    let path = CString::new(dir.as_os_str().as_bytes())?;
+    // SAFETY: the path is NUL-terminated and outlives the call
+    match unsafe { sys::access(path.as_ptr(), sys::X_OK) } {
+        0 => Ok(()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
+
+/// Resolves `.` and `..` in the path textually, without following symlinks.
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            // the parent of the root is the root itself
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// Everything a shell remembers from one command to the next.
+///
+/// The process environment is only read once, when the state is created, so
+/// several states don't see each other's variables or working directory.
+pub struct State {
+    last_exit_code: ExitCode,
+    path: PathBuf,
+    history: History,
+    options: ShellOptions,
+    /// The exported variables, passed on to every program.
+    env: HashMap<String, String>,
+    /// Set by `NAME=value`, only the exported ones are in `env`.
+    variables: HashMap<String, String>,
+    /// Built on first use, see [`State::completion`].
+    completion: Option<Completion>,
+    /// Set by `exit`, the shell stops after the current command.
+    exit: Option<ExitCode>,
+}
+
+impl State {
+    pub fn new() -> Self {
+        let path = Self::working_directory();
+        // like bash, names or values that are not UTF-8 are left out
+        let mut env: HashMap<_, _> = std::env::vars_os()
+            .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+            .collect();
+        env.insert("PWD".to_string(), path.display().to_string());
+        Self {
+            last_exit_code: 0,
+            path,
+            history: History::from_env(),
+            options: ShellOptions::from_env(),
+            env,
+            variables: HashMap::new(),
+            completion: None,
+            exit: None,
+        }
+    }
+
+    /// The status `exit` asked the shell to leave with, if it ran.
+    pub fn exit_requested(&self) -> Option<ExitCode> {
+        self.exit
+    }
+
+    /// The inherited `PWD` if it still names the current directory, so symlinks
+    /// in it are kept, otherwise the physical path.
+    fn working_directory() -> PathBuf {
+        // the directory might have been removed from under us
+        let current = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("/"));
+        match std::env::var_os("PWD").map(PathBuf::from) {
+            Some(pwd)
+                if pwd.is_absolute()
+                    && std::fs::canonicalize(&pwd).is_ok_and(|pwd| pwd == current) =>
+            {
+                normalize(&pwd)
+            }
+            _ => current,
+        }
+    }
+
+    /// The known commands, only rescanning the `PATH` directories that changed.
+    fn completion(&mut self) -> &Completion {
+        let path = self.var("PATH").unwrap_or_default();
+        let completion = match self.completion.take() {
+            Some(mut completion) => {
+                completion.refresh(&path);
+                completion
+            }
+            None => Completion::new(&path),
+        };
+        self.completion.insert(completion)
+    }
+
+    /// The value of a shell variable, falling back to the environment.
+    fn var(&self, name: &str) -> Option<String> {
+        self.variables
+            .get(name)
+            .cloned()
+            .or_else(|| self.env.get(name).cloned())
+    }
+
+    /// Sets a variable, an exported one keeps being exported with the new value.
+    fn assign(&mut self, name: String, value: String) {
+        if let Some(exported) = self.env.get_mut(&name) {
+            *exported = value;
+        } else {
+            self.variables.insert(name, value);
+        }
+    }
+
+    /// Expands and exports the `NAME=value` words in front of a command until
+    /// [`State::restore`], each one already seeing the ones before it.
+    fn assign_temporarily(
+        &mut self,
+        assignments: &[(String, args::Word)],
+    ) -> Result<Vec<Saved>, Errors> {
+        let mut saved = Vec::with_capacity(assignments.len());
+        for (name, value) in assignments {
+            let value = match expand::expand_value(value, self) {
+                Ok(value) => value,
+                Err(e) => {
+                    self.restore(saved);
+                    return Err(e.into());
+                }
+            };
+            saved.push(Saved {
+                env: self.env.insert(name.clone(), value),
+                // would shadow the exported value
+                variable: self.variables.remove(name),
+                name: name.clone(),
+            });
+        }
+        Ok(saved)
+    }
+
+    /// Puts back the variables [`State::assign_temporarily`] replaced.
+    fn restore(&mut self, saved: Vec<Saved>) {
+        // the last one first, in case a name was assigned twice
+        for saved in saved.into_iter().rev() {
+            match saved.env {
+                Some(value) => self.env.insert(saved.name.clone(), value),
+                None => self.env.remove(&saved.name),
+            };
+            if let Some(value) = saved.variable {
+                self.variables.insert(saved.name, value);
+            }
+        }
+    }
+
+    /// Writes the history back to `HISTFILE`, if it was read from there.
+    fn save_history(&self) {
+        if let Err(e) = self.history.save(self.var("HISTTIMEFORMAT").is_some()) {
+            eprintln!("history: {}", redirect::reason(&e));
+        }
+    }
+
+    /// The first directory named `dir` inside the `CDPATH` directories.
+    fn search_cdpath(&self, dir: &str) -> Option<PathBuf> {
+        let cdpath = self.var("CDPATH")?;
+        std::env::split_paths(&cdpath)
+            .filter(|base| !base.as_os_str().is_empty())
+            .map(|base| self.path.join(base).join(dir))
+            .find(|path| path.is_dir())
+    }
+
+    fn is_builtin(com: &str) -> Result<(), Errors> {
+        com.try_into().map(|_: Builtins| ())
+    }
+
+    fn run_builtins(
+        &mut self,
+        com: Builtins,
+        rest: &[&str],
+        // no builtin reads its input yet
+        _stdin: &mut dyn Read,
+        stdout: &mut dyn Write,
+        stderr: &mut dyn Write,
+    ) -> Result<ExitCode, Errors> {
+        match com {
+            Builtins::Exit => {
+                if rest.is_empty() {
+                    return Err(Errors::MissingArgument("exit"));
+                }
+
+                let code = rest[0].parse();
+                if let Ok(c) = code {
+                    // the caller leaves once the command line is done
+                    self.exit = Some(c);
+                    return Ok(c);
+                }
+                Err(Errors::IncorrectArgumentType(
+                    rest[0].to_string(),
+                    "integer",
+                ))
+            }
+            Builtins::Echo => {
+                writeln!(stdout, "{}", rest.join(" "))?;
+                Ok(0)
+            }
+            Builtins::Type => {
+                let Some(&com) = rest.first() else {
+                    return Err(Errors::MissingArgument("type"));
+                };
+                if Self::is_builtin(com).is_ok() {
+                    writeln!(stdout, "{} is a shell builtin", com)?;
+                } else if let Ok(v) = self.is_program(com) {
+                    writeln!(stdout, "{} is {}", com, v)?;
+                } else {
+                    writeln!(stderr, "{} not found", com)?;
+                    return Ok(1);
+                }
+                Ok(0)
+            }
+            Builtins::Pwd => {
+                let mut physical = false;
+                for arg in rest.iter().filter(|arg| arg.starts_with('-')) {
+                    match *arg {
+                        "-L" => physical = false,
+                        "-P" => physical = true,
+                        flag => {
+                            return Err(Errors::IncorrectArgumentType(
+                                flag.to_string(),
+                                "flag like -L or -P",
+                            ))
+                        }
+                    }
+                }
+
+                // a directory removed from under us has no physical path left
+                let real = std::fs::canonicalize(&self.path).unwrap_or_else(|_| self.path.clone());
+                let path = match self.var("PWD").map(PathBuf::from) {
+                    // a stale PWD no longer leads to where we are
+                    Some(pwd)
+                        if !physical
+                            && pwd.is_absolute()
+                            && std::fs::canonicalize(&pwd).is_ok_and(|pwd| pwd == real) =>
+                    {
+                        pwd
+                    }
+                    _ => real,
+                };
+                writeln!(stdout, "{}", path.display())?;
+                Ok(0)
+            }
+            Builtins::Cd => {
+                // opt-in: `cd --to-file some/file` changes into the directory of the file
+                let mut to_file = false;
+                // like bash, symlinks are kept in the path unless -P is given
+                let mut physical = false;
+                let mut rest = rest;
+                while let [flag @ ("--to-file" | "-L" | "-P"), tail @ ..] = rest {
+                    match *flag {
+                        "--to-file" => to_file = true,
+                        "-P" => physical = true,
+                        _ => physical = false,
+                    }
+                    rest = tail;
+                }
+                let Some(&arg) = rest.first() else {
+                    return Err(Errors::MissingArgument("cd"));
+                };
+
+                let mut old = self.path.clone();
+                // absolute
+                let new = if arg.starts_with('/') {
+                    PathBuf::from_str(arg).or(Err(Errors::IncorrectArgument(arg.to_string())))?
+                } else if arg.starts_with('~') {
+                    // home case
+                    let hm = self.var("HOME").ok_or(Errors::HomeNotSet("cd"))?;
+                    let mut hm = PathBuf::from(hm);
+                    hm.push(arg.trim_start_matches('~'));
+                    hm
+                } else {
+                    old.push(arg);
+                    old
+                };
+                // like bash, paths starting with `.` or `..` never go through CDPATH
+                let from_cdpath = match arg.split('/').next() {
+                    Some("." | "..") => None,
+                    _ if new.is_dir() => None,
+                    _ => self.search_cdpath(arg),
+                };
+                let (new, from_cdpath) = match from_cdpath {
+                    Some(new) => (new, true),
+                    None => (new, false),
+                };
+                let new = match new.parent() {
+                    Some(parent) if to_file && new.is_file() => parent.to_path_buf(),
+                    _ => new,
+                };
+
+                let new = if physical { new } else { normalize(&new) };
+
+                let resolved = match std::fs::metadata(&new) {
+                    Ok(metadata) if metadata.is_dir() => searchable(&new)
+                        .and_then(|()| {
+                            if physical {
+                                std::fs::canonicalize(&new)
+                            } else {
+                                Ok(new)
+                            }
+                        })
+                        .map_err(|e| redirect::reason(&e)),
+                    Ok(_) => Err("Not a directory".to_string()),
+                    // missing, or a parent that can't be searched
+                    Err(e) => Err(redirect::reason(&e)),
+                };
+                match resolved {
+                    Ok(path) => {
+                        self.assign("PWD".to_string(), path.display().to_string());
+                        self.path = path;
+                        // bash prints where a CDPATH entry led to
+                        if self.options.cdprint || from_cdpath {
+                            writeln!(stdout, "{}", self.path.display())?;
+                        }
+                        Ok(0)
+                    }
+                    Err(reason) => {
+                        // named the way it was typed, not the resolved path
+                        writeln!(stderr, "cd: {}: {}", arg, reason)?;
+                        Ok(1)
+                    }
+                }
+            }
+            Builtins::History => {
+                match rest {
+                    ["-d"] => return Err(Errors::MissingArgument("history")),
+                    ["-d", offset, ..] => {
+                        offset
+                            .parse()
+                            .ok()
+                            .and_then(|offset| self.history.delete(offset))
+                            .ok_or_else(|| Errors::HistoryOutOfRange(offset.to_string()))?;
+                    }
+                    [] => self.history.print(
+                        self.history.history.len(),
+                        self.var("HISTTIMEFORMAT").as_deref(),
+                        stdout,
+                    )?,
+                    [count, ..] => {
+                        let count = count.parse().or(Err(Errors::IncorrectArgumentType(
+                            count.to_string(),
+                            "integer",
+                        )))?;
+                        self.history
+                            .print(count, self.var("HISTTIMEFORMAT").as_deref(), stdout)?;
+                    }
+                }
+                Ok(0)
+            }
+            Builtins::Compgen => {
+                let Some(flag) = rest.first() else {
+                    return Err(Errors::MissingArgument("compgen"));
+                };
+                let word = rest.get(1).copied().unwrap_or("");
+
+                let candidates = match *flag {
+                    "-b" => self
+                        .completion()
+                        .predictive_search(word)
+                        .filter(|e| e.kind == EntryKind::Builtin)
+                        .map(|e| e.name.clone())
+                        .collect(),
+                    "-c" => self.completion().complete_word(word),
+                    "-d" => self.list_files(word, true),
+                    "-f" => self.list_files(word, false),
+                    "-v" => completion::complete_variable(
+                        word,
+                        self.env.keys().chain(self.variables.keys()),
+                    ),
+                    _ => {
+                        return Err(Errors::IncorrectArgumentType(
+                            flag.to_string(),
+                            "flag like -b, -c, -d, -f or -v",
+                        ))
+                    }
+                };
+
+                for candidate in candidates {
+                    writeln!(stdout, "{}", candidate)?;
+                }
+                Ok(0)
+            }
+            Builtins::Set => {
+                if rest.is_empty() {
+                    // the shell variables shadow the environment, like in expansions
+                    let mut variables: Vec<_> = self
+                        .env
+                        .iter()
+                        .filter(|(name, _)| !self.variables.contains_key(*name))
+                        .chain(&self.variables)
+                        .collect();
+                    variables.sort();
+                    for (name, value) in variables {
+                        writeln!(stdout, "{}={}", name, value)?;
+                    }
+                    return Ok(0);
+                }
+
+                let mut args = rest.iter();
+                while let Some(&arg) = args.next() {
+                    let (enable, flags) = match (arg.strip_prefix('-'), arg.strip_prefix('+')) {
+                        (Some(flags), _) => (true, flags),
+                        (_, Some(flags)) => (false, flags),
+                        _ => return Err(Errors::InvalidOption(arg.to_string())),
+                    };
+                    for flag in flags.chars() {
+                        let name = match flag {
+                            'e' => "errexit",
+                            'C' => "noclobber",
+                            'o' => match args.next() {
+                                Some(name) => name,
+                                // like bash, a lone `-o` lists the options
+                                None => {
+                                    for (name, on) in self.options.list() {
+                                        let state = if on { "on" } else { "off" };
+                                        writeln!(stdout, "{:<15}\t{}", name, state)?;
+                                    }
+                                    continue;
+                                }
+                            },
+                            _ => {
+                                return Err(Errors::InvalidOption(format!("{}{}", &arg[..1], flag)))
+                            }
+                        };
+                        let option = self
+                            .options
+                            .get_mut(name)
+                            .ok_or_else(|| Errors::InvalidOption(name.to_string()))?;
+                        *option = enable;
+                    }
+                }
+                Ok(0)
+            }
+            Builtins::Fc => {
+                let (list, rest) = match rest {
+                    ["-l", rest @ ..] => (true, rest),
+                    _ => (false, rest),
+                };
+                // like bash, listing defaults to the last 16 commands, editing to the last one
+                let first = match rest.first() {
+                    Some(spec) => self.history.find(spec),
+                    // fewer than 16 left, from the oldest one still kept
+                    None if list => self.history.find("-16").or(self.history.oldest()),
+                    None => self.history.find("-1"),
+                }
+                .ok_or(Errors::FcOutOfRange)?;
+                let last = match rest.get(1) {
+                    Some(spec) => self.history.find(spec).ok_or(Errors::FcOutOfRange)?,
+                    None if list => self.history.find("-1").ok_or(Errors::FcOutOfRange)?,
+                    None => first,
+                };
+                let (first, last) = (first.min(last), first.max(last));
+
+                if list {
+                    self.history.print_range(first, last, stdout)?;
+                    return Ok(0);
+                }
+                self.fc_edit(first, last, stdout, stderr)
+            }
+        }
+    }
+
+    /// Opens the history entries from `first` to `last` in `FCEDIT` or
+    /// `EDITOR`, then runs the saved lines like bash's `fc`.
+    fn fc_edit(
+        &mut self,
+        first: usize,
+        last: usize,
+        stdout: &mut dyn Write,
+        stderr: &mut dyn Write,
+    ) -> Result<ExitCode, Errors> {
+        let editor = self
+            .var("FCEDIT")
+            .or_else(|| self.var("EDITOR"))
+            .unwrap_or_else(|| "vi".to_string());
+        // the editor may come with arguments, like `code -w`
+        let mut editor = editor.split_whitespace();
+        let program = editor.next().unwrap_or("vi");
+
+        let mut commands = self.history.history[first..=last].join("\n");
+        commands.push('\n');
+        let file = Self::fc_file(commands.as_bytes())?;
+
+        let status = Command::new(program)
+            .args(editor)
+            .arg(&file)
+            .current_dir(&self.path)
+            .status();
+        let commands = std::fs::read_to_string(&file);
+        let _ = std::fs::remove_file(&file);
+        match status.map_err(|e| Errors::Spawn(program.to_string(), e))? {
+            // like bash, nothing runs when the editor fails
+            status if !status.success() => return Ok(status.code().unwrap_or(1)),
+            _ => {}
+        }
+
+        let mut code = 0;
+        for command in commands?.lines().map(str::trim).filter(|c| !c.is_empty()) {
+            // bash echoes the edited commands before running them
+            writeln!(stdout, "{}", command)?;
+            self.history.add(command);
+            code = match self.run_commands(command, Some(&mut *stdout)) {
+                Ok(code) => code,
+                Err(e) => {
+                    writeln!(stderr, "{}", e)?;
+                    e.exit_code()
+                }
+            };
+            if self.exit.is_some() {
+                break;
+            }
+        }
+        Ok(code)
+    }
+
+    /// Writes the commands to a new file only we can read, for the editor to open.
+    ///
+    /// The name is predictable, so an existing file or a symlink planted
+    /// there is never opened, the next name is tried instead.
+    fn fc_file(commands: &[u8]) -> io::Result<PathBuf> {
+        let mut attempt = 0;
+        loop {
+            let name = format!("fc-{}-{}", std::process::id(), attempt);
+            let path = std::env::temp_dir().join(name);
+            let opened = std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(&path);
+            match opened {
+                Ok(mut file) => {
+                    file.write_all(commands)?;
+                    return Ok(path);
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists && attempt < 100 => attempt += 1,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Lists the files starting with `word`, relative to the current directory.
+    ///
+    /// With `dirs_only`, like for the argument of `cd`, only directories are
+    /// listed and they end in a `/`.
+    fn list_files(&self, word: &str, dirs_only: bool) -> Vec<String> {
+        let (dir, prefix) = match word.rfind('/') {
+            Some(i) => word.split_at(i + 1),
+            None => ("", word),
+        };
+        // the candidates keep the `~` as typed
+        let search = match (dir.strip_prefix("~/"), self.var("HOME")) {
+            (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+            _ => self.path.join(dir),
+        };
+
+        let Ok(entries) = std::fs::read_dir(search) else {
+            return Vec::new();
+        };
+
+        let mut files: Vec<_> = entries
+            .filter_map(Result::ok)
+            // follows symlinks, so a link to a directory counts as one
+            .filter(|e| !dirs_only || e.path().is_dir())
+            .filter_map(|e| e.file_name().into_string().ok())
+            // hidden files are only listed when explicitly asked for
+            .filter(|name| {
+                name.starts_with(prefix) && (prefix.starts_with('.') || !name.starts_with('.'))
+            })
+            .map(|name| {
+                let slash = if dirs_only { "/" } else { "" };
+                format!("{}{}{}", dir, name, slash)
+            })
+            .collect();
+        files.sort();
+        files
+    }
+
+    /// Finds the program in `PATH`, or checks the path as given if it has a `/`.
+    fn is_program(&self, com: &str) -> Result<String, Errors> {
+        // like bash, `./script` and `/bin/ls` are never looked up
+        if com.contains('/') {
+            if completion::is_executable(&self.path.join(com)) {
+                return Ok(com.to_string());
+            }
+            return Err(Errors::CommandNotFound(com.to_string()));
+        }
+        // without a PATH no program can be found
+        let paths = self.var("PATH").unwrap_or_default();
+        let mut pbuf = PathBuf::new();
+        for path in paths
+            .split(':')
+            .map(str::trim)
+            .filter(|path| !path.is_empty())
+        {
+            pbuf.clear();
+            pbuf.push(path);
+            pbuf.push(com);
+            if completion::is_executable(&pbuf) {
+                if let Some(path) = pbuf.to_str() {
+                    return Ok(path.to_string());
+                }
+            }
+        }
+        Err(Errors::CommandNotFound(com.to_string()))
+    }
+
+    fn run_program(
+        &self,
+        com: &str,
+        rest: &[&str],
+        stdin: Stdio,
+        stdout: Stdio,
+        stderr: Stdio,
+    ) -> Result<Child, Errors> {
+        // a path runs as it is, so a missing or non-executable file is reported as such
+        let path = if com.contains('/') {
+            com.to_string()
+        } else {
+            self.is_program(com)?
+        };
+        // relative to the shell's working directory, not the one of the process,
+        // while the program sees its name as typed, like in bash
+        let child = Command::new(self.path.join(&path))
+            .arg0(com)
+            .args(rest)
+            .env_clear()
+            .envs(&self.env)
+            .current_dir(&self.path)
+            .stdin(stdin)
+            .stdout(stdout)
+            .stderr(stderr)
+            .spawn()
+            .map_err(|e| Errors::Spawn(com.to_string(), e))?;
+        Ok(child)
+    }
+
+    /// The words of every command in the line after expansion, the way the
+    /// commands would receive them, without running anything.
+    ///
+    /// Expansions with side effects, like `${NAME:=value}`, still take effect.
+    pub fn process_args(&mut self, line: &str) -> Result<Vec<Vec<String>>, Errors> {
+        let mut commands = Vec::new();
+        for command in args::parse_list(line)?.iter().flatten() {
+            let assignments = command
+                .words
+                .iter()
+                .map_while(args::Word::assignment)
+                .count();
+            commands.push(expand::expand(&command.words[assignments..], self)?);
+        }
+        Ok(commands)
+    }
+
+    /// Runs the pipelines one line after the other, their last commands write
+    /// to `out`, or the shell's stdout when `None`.
+    ///
+    /// Only the error of the last pipeline is returned, the earlier ones are
+    /// printed like in a script. Stops early after `exit`, see
+    /// [`State::exit_requested`].
+    pub fn run_commands(
+        &mut self,
+        command: &str,
+        out: Option<&mut dyn Write>,
+    ) -> Result<ExitCode, Errors> {
+        self.run_list(command, out, &mut |e| eprintln!("{}", e))
+    }
+
+    /// Like [`State::run_commands`], but the errors of the earlier pipelines
+    /// are handed to `errors` instead of printed.
+    fn run_list(
+        &mut self,
+        command: &str,
+        mut out: Option<&mut dyn Write>,
+        errors: &mut dyn FnMut(Errors),
+    ) -> Result<ExitCode, Errors> {
+        let list = args::parse_list(command)?;
+        let Some((last, pipelines)) = list.split_last() else {
+            return Ok(0);
+        };
+        for pipeline in pipelines {
+            self.last_exit_code = match self.run_pipeline(pipeline, out.as_deref_mut()) {
+                Ok(code) => code,
+                Err(e) => {
+                    let code = e.exit_code();
+                    errors(e);
+                    code
+                }
+            };
+            if self.exit.is_some() || (self.options.errexit && self.last_exit_code != 0) {
+                return Ok(self.last_exit_code);
+            }
+        }
+        self.run_pipeline(last, out)
+    }
+
+    fn run_pipeline(
+        &mut self,
+        pipeline: &args::Pipeline,
+        mut out: Option<&mut (dyn Write + '_)>,
+    ) -> Result<ExitCode, Errors> {
+        let mut stages = Vec::with_capacity(pipeline.len());
+
+        let result = self.run_commands_stages(pipeline, &mut stages, out.as_deref_mut());
+        // like in bash, where every stage runs in a subshell, `exit | cat` keeps going
+        if pipeline.len() > 1 {
+            self.exit = None;
+        }
+        match result {
+            Ok(last_stdout) => {
+                let codes = Self::run_commands_post(stages, last_stdout, out)?;
+                // like bash, the pipeline reports the status of its last command,
+                // or with pipefail of the last one that failed
+                let code = if self.options.pipefail {
+                    codes.iter().rev().find(|code| **code != 0)
+                } else {
+                    codes.last()
+                };
+                Ok(code.copied().unwrap_or(0))
+            }
+            Err(e) => {
+                // the stages before the failing one are already running
+                for stage in stages {
+                    let _ = stage.wait();
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Starts every stage of the pipeline, connecting each stdout to the next stdin.
+    fn run_commands_stages(
+        &mut self,
+        pipeline: &args::Pipeline,
+        stages: &mut Vec<Stage>,
+        mut out: Option<&mut (dyn Write + '_)>,
+    ) -> Result<LastStdout, Errors> {
+        let mut last_stdout = LastStdout::None;
+
+        for (i, command) in pipeline.iter().enumerate() {
+            let last = i + 1 == pipeline.len();
+            let output = match out.as_deref_mut() {
+                _ if !last => Output::Pipe,
+                Some(out) => Output::Captured(out),
+                None => Output::Stdout,
+            };
+            // the leading `NAME=value` words
+            let assignments: Vec<_> = command
+                .words
+                .iter()
+                .map_while(args::Word::assignment)
+                .collect();
+            let words = &command.words[assignments.len()..];
+
+            // like in bash, the assignments are expanded last
+            let args = expand::expand(words, self)?;
+            let redirects = self.open_redirects(&command.redirects)?;
+            // a here-document or here-string replaces whatever came through the pipe
+            if let Some(stdin) = &command.stdin {
+                last_stdout = LastStdout::Buffer(expand::expand_value(stdin, self)?.into_bytes());
+            }
+            let Some((com, rest)) = args.split_first() else {
+                // without a command, the assignments are left behind and the
+                // later ones already see the earlier ones
+                for (name, value) in assignments {
+                    let value = expand::expand_value(&value, self)?;
+                    self.assign(name, value);
+                }
+                last_stdout = LastStdout::Buffer(Vec::new());
+                stages.push(Stage::Done(0));
+                continue;
+            };
+            let parts: Vec<_> = rest.iter().map(String::as_str).collect();
+            // only for the command, exported to it like for a program
+            let saved = self.assign_temporarily(&assignments)?;
+
+            if let Ok(builtin) = com.as_str().try_into() {
+                let result =
+                    self.run_commands_builtin(builtin, &parts, last_stdout, output, redirects);
+                self.restore(saved);
+                let (stdout, code) = result?;
+                last_stdout = stdout;
+                stages.push(Stage::Done(code));
+            } else {
+                let result = self.run_commands_program(com, &parts, last_stdout, output, redirects);
+                self.restore(saved);
+                let (mut child, merged) = result?;
+                // a redirected stdout leaves nothing for the next stage to read
+                last_stdout = match (merged, child.stdout.take()) {
+                    (Some(merged), _) => LastStdout::Pipe(merged),
+                    (None, Some(stdout)) => LastStdout::Child(stdout),
+                    (None, None) => LastStdout::Buffer(Vec::new()),
+                };
+                stages.push(Stage::Running(child));
+            }
+        }
+
+        Ok(last_stdout)
+    }
+
+    /// Opens the files of every redirect, in the order they were written.
+    fn open_redirects(&mut self, redirects: &[args::Redirect]) -> Result<Redirects, Errors> {
+        let mut opened = Redirects::default();
+        for redirect in redirects {
+            let target = expand::expand(std::slice::from_ref(&redirect.target), self)?;
+            let [target] = target.as_slice() else {
+                return Err(redirect::Error::Ambiguous(redirect.target.literal()).into());
+            };
+            let (fd, mode) = match (redirect.fd, redirect.mode) {
+                (fd, args::Mode::Duplicate) if target.bytes().all(|b| b.is_ascii_digit()) => {
+                    opened.duplicate(fd, target)?;
+                    continue;
+                }
+                // like bash, `>&file` is `&>file`
+                (args::Fd::Single(1), args::Mode::Duplicate) => {
+                    (args::Fd::Both, args::Mode::Truncate)
+                }
+                (_, args::Mode::Duplicate) => {
+                    return Err(redirect::Error::BadFd(target.to_string()).into())
+                }
+                (fd, mode) => (fd, mode),
+            };
+            opened.add(
+                fd,
+                target,
+                &self.path.join(target),
+                mode,
+                self.options.noclobber,
+            )?;
+        }
+        Ok(opened)
+    }
+
+    /// Runs a builtin to completion.
+    ///
+    /// Whatever the builtin writes shows up in the order it was written:
+    /// redirected streams go straight to their file, and the terminal's stdout
+    /// is flushed before anything is written to stderr.
+    fn run_commands_builtin(
+        &mut self,
+        com: Builtins,
+        rest: &[&str],
+        last_stdout: LastStdout,
+        output: Output,
+        redirects: Redirects,
+    ) -> Result<(LastStdout, ExitCode), Errors> {
+        let mut stdin: Box<dyn Read> = match last_stdout {
+            LastStdout::None => Box::new(io::stdin()),
+            LastStdout::Child(stdout) => Box::new(stdout),
+            LastStdout::Pipe(stdout) => Box::new(stdout),
+            LastStdout::Buffer(buffer) => Box::new(io::Cursor::new(buffer)),
+        };
+        // only the output of a builtin in the middle of a pipeline is kept for the next stage
+        let mut buffer = Vec::new();
+        let output: Box<dyn Write> = match output {
+            Output::Stdout => Box::new(io::stdout()),
+            Output::Captured(out) => Box::new(out),
+            Output::Pipe => Box::new(&mut buffer),
+        };
+        // stderr may write there as well, with `2>&1`
+        let output = RefCell::new(output);
+        let mut stdout: Box<dyn Write> = match redirects.stdout {
+            Some(file) => Box::new(file),
+            None if redirects.stdout_to_stderr => Box::new(FlushStdout(io::stderr())),
+            None => Box::new(Shared(&output)),
+        };
+        let mut stderr: Box<dyn Write> = match redirects.stderr {
+            Some(file) => Box::new(file),
+            None if redirects.stderr_to_stdout => Box::new(Shared(&output)),
+            None => Box::new(FlushStdout(io::stderr())),
+        };
+
+        let code = match self.run_builtins(com, rest, &mut stdin, &mut stdout, &mut stderr) {
+            Ok(code) => code,
+            // like a program, a failing builtin complains on stderr and the pipeline goes on
+            Err(e) => {
+                writeln!(stderr, "{}", e)?;
+                e.exit_code()
+            }
+        };
+        stdout.flush()?;
+        stderr.flush()?;
+        drop((stdout, stderr));
+        drop(output);
+
+        Ok((LastStdout::Buffer(buffer), code))
+    }
+
+    fn run_commands_program(
+        &self,
+        com: &str,
+        rest: &[&str],
+        last_stdout: LastStdout,
+        output: Output,
+        redirects: Redirects,
+    ) -> Result<(Child, Option<io::PipeReader>), Errors> {
+        let (stdin, pending) = match last_stdout {
+            LastStdout::None => (Stdio::inherit(), None),
+            LastStdout::Child(stdout) => (Stdio::from(stdout), None),
+            LastStdout::Pipe(stdout) => (Stdio::from(stdout), None),
+            LastStdout::Buffer(buffer) => (Stdio::piped(), Some(buffer)),
+        };
+        // with `2>&1` into a pipe, the pipe has to exist to hand it out twice
+        let (merged, writer) = match output {
+            Output::Captured(_) | Output::Pipe if redirects.stderr_to_stdout => {
+                let (reader, writer) = io::pipe()?;
+                (Some(reader), Some(writer))
+            }
+            _ => (None, None),
+        };
+        let stdout = match (redirects.stdout, output, &writer) {
+            (Some(file), _, _) => Stdio::from(file),
+            (None, _, _) if redirects.stdout_to_stderr => {
+                Stdio::from(io::stderr().as_fd().try_clone_to_owned()?)
+            }
+            (None, _, Some(writer)) => Stdio::from(writer.try_clone()?),
+            (None, Output::Stdout, None) => Stdio::inherit(),
+            (None, Output::Captured(_) | Output::Pipe, None) => Stdio::piped(),
+        };
+        let stderr = match (redirects.stderr, writer) {
+            (Some(file), _) => Stdio::from(file),
+            (None, Some(writer)) => Stdio::from(writer),
+            (None, None) if redirects.stderr_to_stdout => {
+                Stdio::from(io::stdout().as_fd().try_clone_to_owned()?)
+            }
+            (None, None) => Stdio::inherit(),
+        };
+
+        let mut child = self.run_program(com, rest, stdin, stdout, stderr)?;
+        if let (Some(buffer), Some(mut stdin)) = (pending, child.stdin.take()) {
+            // written from a thread, the child might fill its stdout before reading everything
+            std::thread::spawn(move || stdin.write_all(&buffer));
+        }
+        Ok((child, merged))
+    }
+
+    /// Copies the output of a captured last program to `out` and waits for
+    /// every program, returning the exit code of every stage in order.
+    fn run_commands_post(
+        stages: Vec<Stage>,
+        last_stdout: LastStdout,
+        out: Option<&mut (dyn Write + '_)>,
+    ) -> Result<Vec<ExitCode>, Errors> {
+        // the captured output is only read once every stage runs
+        let copied = match (out, last_stdout) {
+            (Some(out), LastStdout::Child(mut stdout)) => io::copy(&mut stdout, out).map(|_| ()),
+            (Some(out), LastStdout::Pipe(mut stdout)) => io::copy(&mut stdout, out).map(|_| ()),
+            _ => Ok(()),
+        };
+
+        // every child is waited on, even after a failure, so none is left a zombie
+        let codes: Vec<_> = stages.into_iter().map(Stage::wait).collect();
+        copied?;
+        codes.into_iter().collect()
+    }
+}
+
+/// A variable as it was before [`State::assign_temporarily`].
+struct Saved {
+    name: String,
+    env: Option<String>,
+    variable: Option<String>,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The output of the previous stage of a pipeline.
+enum LastStdout {
+    /// Nothing came before, the stage reads from the terminal.
+    None,
+    Child(ChildStdout),
+    /// Both stdout and stderr of a program, after `2>&1`.
+    Pipe(io::PipeReader),
+    /// What a builtin wrote.
+    Buffer(Vec<u8>),
+}
+
+/// Where the last stage of a pipeline writes to.
+enum Output<'a> {
+    /// Not the last stage, into a pipe to the next one.
+    Pipe,
+    /// The shell's own stdout, inherited by programs.
+    Stdout,
+    /// Handed to the caller, programs write to it through a pipe.
+    Captured(&'a mut dyn Write),
+}
+
+/// Writes to a writer that stdout and stderr share, after `2>&1`.
+struct Shared<'a, W>(&'a RefCell<W>);
+
+impl<W: Write> Write for Shared<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
+
+/// Flushes stdout before every write, keeping both streams in order on the terminal.
+struct FlushStdout<W>(W);
+
+impl<W: Write> Write for FlushStdout<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        io::stdout().flush()?;
+        self.0.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.0.flush()
+    }
+}
+
+/// A stage of a pipeline that has been started.
+enum Stage {
+    /// Builtins run to completion before the next stage starts.
+    Done(ExitCode),
+    Running(Child),
+}
+
+impl Stage {
+    fn wait(self) -> Result<ExitCode, Errors> {
+        match self {
+            Self::Done(code) => Ok(code),
+            Self::Running(mut child) => {
+                let status = child.wait()?;
+                let Some(number) = status.signal() else {
+                    return Ok(status.code().unwrap_or(0));
+                };
+                // like bash, an interrupted program doesn't need an explanation
+                if let Some(description) = signal::describe(number) {
+                    let core = if status.core_dumped() {
+                        " (core dumped)"
+                    } else {
+                        ""
+                    };
+                    eprintln!("{}{}", description, core);
+                }
+                Ok(128 + number)
+            }
+        }
+    }
+}
+
+/// A shell to embed, every command line runs against the same state.
+pub struct Shell {
+    state: State,
+    /// The errors of the pipelines that didn't end their line, see [`Shell::take_errors`].
+    errors: Vec<Errors>,
+}
+
+impl Shell {
+    pub fn new() -> Self {
+        Self {
+            state: State::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    /// Runs a command line, writing what its last command prints to `out`.
+    ///
+    /// Unlike the interactive shell, errors are returned instead of printed:
+    /// the one of the last pipeline as the result, the earlier ones, after
+    /// which the line goes on, through [`Shell::take_errors`]. `$?` is
+    /// updated either way. After `exit` the rest of the line is skipped, and
+    /// it is up to the caller to stop, see [`Shell::exit_requested`].
+    pub fn run(&mut self, input: &str, out: &mut dyn Write) -> Result<ExitCode, Errors> {
+        let errors = &mut self.errors;
+        let result = self
+            .state
+            .run_list(input, Some(out), &mut |e| errors.push(e));
+        self.state.last_exit_code = match &result {
+            Ok(code) => *code,
+            Err(e) => e.exit_code(),
+        };
+        result
+    }
+
+    /// Takes the errors of the pipelines that failed before the last one of
+    /// their line, in the order they happened.
+    pub fn take_errors(&mut self) -> Vec<Errors> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// The status `exit` asked to leave with, once it ran.
+    pub fn exit_requested(&self) -> Option<ExitCode> {
+        self.state.exit_requested()
+    }
+
+    /// The state the command lines run against, to reach [`State::process_args`].
+    pub fn state_mut(&mut self) -> &mut State {
+        &mut self.state
+    }
+}
+
+impl Default for Shell {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Runs the command lines of the string without a prompt, returning the last exit code.
+pub fn run_string(command: &str) -> ExitCode {
+    run_script(command.as_bytes())
+}
+
+/// Runs the script at `path`, returning the last exit code.
+pub fn run_file(path: &str) -> ExitCode {
+    match std::fs::File::open(path) {
+        Ok(file) => run_script(io::BufReader::new(file)),
+        Err(e) => {
+            eprintln!("{}: {}", path, redirect::reason(&e));
+            127
+        }
+    }
+}
+
+/// Appends the next line to `line`, replacing invalid UTF-8 instead of failing on it.
+fn read_line(input: &mut impl BufRead, line: &mut String) -> io::Result<usize> {
+    let mut bytes = Vec::new();
+    let read = input.read_until(b'\n', &mut bytes)?;
+    line.push_str(&String::from_utf8_lossy(&bytes));
+    Ok(read)
+}
+
+/// Like [`read_line`], but gives up with [`io::ErrorKind::Interrupted`] when
+/// Ctrl-C interrupts the read instead of reading on.
+fn read_prompt_line(input: &mut impl BufRead, line: &mut String) -> io::Result<usize> {
+    let _interrupt = signal::CatchInterrupt::interrupting();
+    let mut bytes = Vec::new();
+    loop {
+        let buf = input.fill_buf()?;
+        let (len, done) = match buf.iter().position(|&b| b == b'\n') {
+            Some(i) => (i + 1, true),
+            None => (buf.len(), buf.is_empty()),
+        };
+        bytes.extend_from_slice(&buf[..len]);
+        input.consume(len);
+        if done {
+            break;
+        }
+    }
+    line.push_str(&String::from_utf8_lossy(&bytes));
+    Ok(bytes.len())
+}
+
+/// The EOFs in a row `ignoreeof` made the interactive shell ignore.
+struct EofCount {
+    count: i32,
+    /// How many are ignored before the shell exits anyway, from `IGNOREEOF`.
+    max: i32,
+}
+
+impl EofCount {
+    fn new(max: i32) -> Self {
+        Self { count: 0, max }
+    }
+
+    fn from_env() -> Self {
+        // bash falls back to 10 when IGNOREEOF is not a number
+        let max = std::env::var("IGNOREEOF")
+            .ok()
+            .and_then(|count| count.parse().ok())
+            .unwrap_or(10);
+        Self::new(max)
+    }
+
+    /// Whether the EOF just read is ignored instead of ending the shell,
+    /// `ignoreeof` being whether the option applies.
+    fn ignore(&mut self, ignoreeof: bool) -> bool {
+        if ignoreeof && self.count < self.max {
+            self.count += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// A line was read, so the next EOF starts the count over.
+    fn reset(&mut self) {
+        self.count = 0;
+    }
+}
+
+/// Runs the lines one after the other without a prompt, returning the last exit code.
+pub fn run_script(mut input: impl BufRead) -> ExitCode {
+    let mut state = State::new();
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        match read_line(&mut input, &mut line) {
+            Ok(0) => break,
+            Ok(_) => {}
+            Err(e) => {
+                eprintln!("{}", redirect::reason(&e));
+                return 1;
+            }
+        }
+        // unterminated quotes and trailing backslashes continue on the next line
+        while args::parse_list(&line).is_err_and(|e| e.is_incomplete()) {
+            match read_line(&mut input, &mut line) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) => {
+                    eprintln!("{}", redirect::reason(&e));
+                    return 1;
+                }
+            }
+        }
+
+        let command = line.trim();
+        if command.is_empty() {
+            continue;
+        }
+        state.last_exit_code = match state.run_commands(command, None) {
+            Ok(code) => code,
+            Err(e) => {
+                eprintln!("{}", e);
+                e.exit_code()
+            }
+        };
+        if let Some(code) = state.exit {
+            return code;
+        }
+        // `set -e` stops at the first failing command
+        if state.options.errexit && state.last_exit_code != 0 {
+            break;
+        }
+    }
+
+    io::stdout().flush().unwrap();
+    state.last_exit_code
+}
+
+/// Reads and runs command lines until the input ends, returning the last exit code.
+pub fn repl() -> ExitCode {
+    let stdin = io::stdin();
+    let mut input = String::new();
+
+    let mut state = State::new();
+    // like bash, only the interactive shell starts from the saved history
+    if let Some(path) = history::histfile() {
+        // a missing or unreadable file starts an empty history
+        let _ = state.history.read_file(&path);
+    }
+
+    let mut eof_count = EofCount::from_env();
+
+    'prompt: loop {
+        input.clear();
+
+        // add promt
+        print!("{}", prompt::render(&state));
+        io::stdout().flush().unwrap();
+        let size = match read_prompt_line(&mut stdin.lock(), &mut input) {
+            Ok(size) => size,
+            // Ctrl-C drops the line and starts over on a fresh one, like bash
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                println!();
+                state.last_exit_code = 130;
+                continue;
+            }
+            Err(e) => {
+                eprintln!("{}", redirect::reason(&e));
+                break;
+            }
+        };
+        if size == 0 {
+            println!();
+            if eof_count.ignore(state.options.ignoreeof && stdin.is_terminal()) {
+                println!("Use \"exit\" to leave the shell.");
+                io::stdout().flush().unwrap();
+                continue;
+            }
+            break;
+        }
+        eof_count.reset();
+
+        // unterminated quotes and trailing backslashes continue on the next line
+        while args::parse_list(&input).is_err_and(|e| e.is_incomplete()) {
+            print!("{}", prompt::render_continuation(&state));
+            io::stdout().flush().unwrap();
+            match read_prompt_line(&mut stdin.lock(), &mut input) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                    println!();
+                    state.last_exit_code = 130;
+                    continue 'prompt;
+                }
+                Err(e) => {
+                    eprintln!("{}", redirect::reason(&e));
+                    break 'prompt;
+                }
+            }
+        }
+
+        let line = match state.history.expand(&input) {
+            Ok(Some(expanded)) => {
+                println!("{}", expanded.trim());
+                io::stdout().flush().unwrap();
+                Cow::Owned(expanded)
+            }
+            Ok(None) => Cow::Borrowed(input.as_str()),
+            Err(e) => {
+                println!("{}", e);
+                io::stdout().flush().unwrap();
+                continue;
+            }
+        };
+        let input = line.trim();
+        if input.is_empty() {
+            continue;
+        }
+        // the line as it was typed, not how it was parsed
+        if args::parse_list(input).is_ok() {
+            state.history.add(&line);
+        } else {
+            state.history.add_invalid(&line);
+        }
+        // Ctrl-C interrupts the running programs, not the interactive shell,
+        // while a script or -c is interrupted as a whole
+        let interrupt = signal::CatchInterrupt::new();
+        let result = state.run_commands(input, None);
+        drop(interrupt);
+        state.last_exit_code = match result {
+            Ok(code) => code,
+            Err(e) => {
+                // after whatever the command printed so far
+                io::stdout().flush().unwrap();
+                eprintln!("{}", e);
+                e.exit_code()
+            }
+        };
+
+        if let Some(code) = state.exit {
+            state.last_exit_code = code;
+            break;
+        }
+        if state.options.errexit && state.last_exit_code != 0 {
+            break;
+        }
+
+        // read input
+        // process
+        // output processed
+    }
+
+    state.save_history();
+    state.last_exit_code
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn eof_count() {
+        let mut eof = EofCount::new(2);
+        assert!(eof.ignore(true));
+        assert!(eof.ignore(true));
+        assert!(!eof.ignore(true));
+
+        // a line in between starts over
+        let mut eof = EofCount::new(2);
+        assert!(eof.ignore(true));
+        eof.reset();
+        assert!(eof.ignore(true));
+        assert!(eof.ignore(true));
+        assert!(!eof.ignore(true));
+
+        // without ignoreeof the first EOF ends the shell
+        assert!(!EofCount::new(10).ignore(false));
+        assert!(!EofCount::new(0).ignore(true));
+        assert!(!EofCount::new(-1).ignore(true));
+    }
+}
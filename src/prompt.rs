@@ -0,0 +1,144 @@
+use std::path::Path;
+
+use crate::State;
+
+/// Used when `PS1` is not set.
+pub const DEFAULT_PROMPT: &str = "$ ";
+
+/// Used when `PS2` is not set.
+pub const DEFAULT_CONTINUATION_PROMPT: &str = "> ";
+
+/// Renders `PS1`, or the default prompt when it is not set.
+pub fn render(state: &State) -> String {
+    match state.var("PS1") {
+        Some(ps1) => render_ps1(&ps1, state),
+        None => DEFAULT_PROMPT.to_string(),
+    }
+}
+
+/// Renders `PS2`, shown while the input continues on the next line.
+pub fn render_continuation(state: &State) -> String {
+    match state.var("PS2") {
+        Some(ps2) => render_ps1(&ps2, state),
+        None => DEFAULT_CONTINUATION_PROMPT.to_string(),
+    }
+}
+
+/// Expands variables and the `\u`, `\h`, `\H`, `\w`, `\$` and `\\`
+/// escapes in `ps1`.
+pub fn render_ps1(ps1: &str, state: &State) -> String {
+    let mut prompt = String::with_capacity(ps1.len());
+    let mut chars = ps1.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('u') => prompt.push_str(&user(state)),
+                Some('h') => prompt.push_str(hostname().split('.').next().unwrap_or_default()),
+                Some('H') => prompt.push_str(&hostname()),
+                Some('w') => prompt.push_str(&working_directory(&state.path, state)),
+                Some('$') => prompt.push(if is_root() { '#' } else { '$' }),
+                Some('\\') => prompt.push('\\'),
+                Some(other) => {
+                    prompt.push('\\');
+                    prompt.push(other);
+                }
+                None => prompt.push('\\'),
+            },
+            '$' => {
+                let braced = chars.next_if_eq(&'{').is_some();
+                let mut name = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+                    name.push(c);
+                }
+                if braced && chars.next_if_eq(&'}').is_none() {
+                    // not a valid reference, keep it as typed
+                    prompt.push_str("${");
+                    prompt.push_str(&name);
+                } else if name.is_empty() {
+                    prompt.push('$');
+                } else {
+                    prompt.push_str(&state.var(&name).unwrap_or_default());
+                }
+            }
+            _ => prompt.push(c),
+        }
+    }
+
+    prompt
+}
+
+fn user(state: &State) -> String {
+    state
+        .var("USER")
+        .or_else(|| state.var("LOGNAME"))
+        .unwrap_or_default()
+}
+
+fn hostname() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/hostname")
+        .or_else(|_| std::fs::read_to_string("/etc/hostname"))
+        .map(|name| name.trim().to_string())
+        .or_else(|_| std::env::var("HOSTNAME"))
+        .unwrap_or_default()
+}
+
+/// The current directory with `HOME` abbreviated to `~`.
+fn working_directory(cwd: &Path, state: &State) -> String {
+    if let Some(home) = state.var("HOME") {
+        match cwd.strip_prefix(home) {
+            Ok(rest) if rest.as_os_str().is_empty() => return "~".to_string(),
+            Ok(rest) => return Path::new("~").join(rest).display().to_string(),
+            Err(_) => {}
+        }
+    }
+    cwd.display().to_string()
+}
+
+#[cfg(unix)]
+fn is_root() -> bool {
+    // SAFETY: geteuid has no preconditions and can't fail
+    unsafe { crate::sys::geteuid() == 0 }
+}
+
+#[cfg(not(unix))]
+fn is_root() -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn variables_and_escapes() {
+        let mut state = State::new();
+        state.assign("USER".to_string(), "alice".to_string());
+        state.assign("HOME".to_string(), "/home/alice".to_string());
+        state.path = "/home/alice/src".into();
+
+        assert_eq!(render_ps1("$USER> ", &state), "alice> ");
+        assert_eq!(render_ps1("${USER}@", &state), "alice@");
+        assert_eq!(render_ps1(r"\u:\w", &state), "alice:~/src");
+        assert_eq!(render_ps1(r"${USER \\ $ \q", &state), r"${USER \ $ \q");
+
+        state.path = "/tmp".into();
+        assert_eq!(render_ps1(r"\w", &state), "/tmp");
+
+        let root = unsafe { crate::sys::geteuid() } == 0;
+        assert_eq!(render_ps1(r"\$", &state), if root { "#" } else { "$" });
+    }
+
+    #[test]
+    fn defaults() {
+        let mut state = State::new();
+        state.env.remove("PS1");
+        state.env.remove("PS2");
+        assert_eq!(render(&state), DEFAULT_PROMPT);
+        assert_eq!(render_continuation(&state), DEFAULT_CONTINUATION_PROMPT);
+
+        state.assign("PS1".to_string(), "$USER$ ".to_string());
+        state.assign("USER".to_string(), "bob".to_string());
+        assert_eq!(render(&state), "bob$ ");
+    }
+}
@@ -0,0 +1,269 @@
+//! Opens the files the output of a command is redirected to.
+
+use std::{
+    fs::{File, OpenOptions},
+    io,
+    path::Path,
+};
+
+use crate::args::{Fd, Mode};
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("{0}: {}", reason(.1))]
+    Open(String, io::Error),
+    #[error("{0}: ambiguous redirect")]
+    Ambiguous(String),
+    #[error("{0}: cannot overwrite existing file")]
+    Clobber(String),
+    #[error("{0}: Bad file descriptor")]
+    BadFd(String),
+}
+
+/// The files the standard streams of a command go to, if redirected.
+#[derive(Debug, Default)]
+pub struct Redirects {
+    pub stdout: Option<File>,
+    pub stderr: Option<File>,
+    /// `2>&1` while stdout was not in a file, so stderr goes where the
+    /// command's stdout would have: the terminal or the pipe.
+    pub stderr_to_stdout: bool,
+    /// `>&2` while stderr was not in a file, so stdout goes to the shell's stderr.
+    pub stdout_to_stderr: bool,
+}
+
+impl Redirects {
+    /// Opens the file for the descriptor, replacing an earlier redirect of it.
+    ///
+    /// `name` is the target as written, used in error messages. With
+    /// `noclobber` an existing file is only overwritten through `>|`.
+    pub fn add(
+        &mut self,
+        fd: Fd,
+        name: &str,
+        path: &Path,
+        mode: Mode,
+        noclobber: bool,
+    ) -> Result<(), Error> {
+        let file = open(path, mode, noclobber).map_err(|e| match e.kind() {
+            io::ErrorKind::AlreadyExists => Error::Clobber(name.to_string()),
+            _ => Error::Open(name.to_string(), e),
+        })?;
+        match fd {
+            Fd::Single(1) => {
+                self.stdout = Some(file);
+                self.stdout_to_stderr = false;
+            }
+            Fd::Single(2) => {
+                self.stderr = Some(file);
+                self.stderr_to_stdout = false;
+            }
+            // like bash the file is still created, but nothing writes to it
+            Fd::Single(_) => {}
+            // a single open file, so both streams share the offset and don't overwrite each other
+            Fd::Both => {
+                let stderr = file
+                    .try_clone()
+                    .map_err(|e| Error::Open(name.to_string(), e))?;
+                self.stdout = Some(file);
+                self.stderr = Some(stderr);
+                self.stdout_to_stderr = false;
+                self.stderr_to_stdout = false;
+            }
+        }
+        Ok(())
+    }
+
+    /// Points the descriptor at wherever `target` goes right now, like `2>&1`.
+    pub fn duplicate(&mut self, fd: Fd, target: &str) -> Result<(), Error> {
+        let clone = |file: &File| {
+            file.try_clone()
+                .map_err(|e| Error::Open(target.to_string(), e))
+        };
+        match (fd, target) {
+            (Fd::Single(2), "1") => {
+                // with `>&2` before, stdout already goes to the original stderr
+                self.stderr = self.stdout.as_ref().map(clone).transpose()?;
+                self.stderr_to_stdout = self.stderr.is_none() && !self.stdout_to_stderr;
+            }
+            (Fd::Single(1), "2") => {
+                self.stdout = self.stderr.as_ref().map(clone).transpose()?;
+                self.stdout_to_stderr = self.stdout.is_none() && !self.stderr_to_stdout;
+            }
+            (Fd::Single(1), "1") | (Fd::Single(2), "2") => {}
+            // like files, the other descriptors are not passed on
+            (Fd::Single(fd), _) if fd > 2 && target.bytes().all(|b| b.is_ascii_digit()) => {}
+            _ => return Err(Error::BadFd(target.to_string())),
+        }
+        Ok(())
+    }
+}
+
+/// The message of the error without the `(os error N)` suffix, like bash prints it.
+pub fn reason(e: &io::Error) -> String {
+    let message = e.to_string();
+    match message.rsplit_once(" (os error ") {
+        Some((reason, _)) => reason.to_string(),
+        None => message,
+    }
+}
+
+fn open(path: &Path, mode: Mode, noclobber: bool) -> io::Result<File> {
+    // character devices like /dev/null have nothing to truncate or protect
+    let regular = std::fs::metadata(path).map_or(true, |m| m.is_file());
+
+    let mut options = OpenOptions::new();
+    options.write(true);
+    match mode {
+        Mode::Append => options.append(true).create(true),
+        Mode::Truncate if noclobber && regular => options.create_new(true),
+        // `>&` only gets here with a file name instead of a descriptor
+        Mode::Truncate | Mode::Clobber | Mode::Duplicate => options.create(true).truncate(regular),
+    };
+    options.open(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, os::unix::fs::PermissionsExt};
+
+    use super::*;
+
+    #[test]
+    fn open_errors() {
+        let dir = std::env::temp_dir().join(format!("redirect-errors-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut redirects = Redirects::default();
+
+        let missing = dir.join("missing").join("out");
+        let error = redirects
+            .add(
+                Fd::Single(2),
+                "missing/out",
+                &missing,
+                Mode::Truncate,
+                false,
+            )
+            .unwrap_err();
+        assert_eq!(error.to_string(), "missing/out: No such file or directory");
+
+        let error = redirects
+            .add(Fd::Single(1), "dir", &dir, Mode::Truncate, false)
+            .unwrap_err();
+        assert_eq!(error.to_string(), "dir: Is a directory");
+
+        let read_only = dir.join("read-only");
+        std::fs::write(&read_only, "").unwrap();
+        std::fs::set_permissions(&read_only, std::fs::Permissions::from_mode(0o444)).unwrap();
+        match redirects.add(Fd::Single(1), "read-only", &read_only, Mode::Append, false) {
+            Err(error) => assert_eq!(error.to_string(), "read-only: Permission denied"),
+            // root writes anyway
+            Ok(()) => assert_eq!(unsafe { crate::sys::geteuid() }, 0),
+        }
+        assert!(redirects.stderr.is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn noclobber() {
+        let dir = std::env::temp_dir().join(format!("redirect-noclobber-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let out = dir.join("out");
+        std::fs::write(&out, "kept").unwrap();
+
+        let mut redirects = Redirects::default();
+        let error = redirects
+            .add(Fd::Single(1), "out", &out, Mode::Truncate, true)
+            .unwrap_err();
+        assert_eq!(error.to_string(), "out: cannot overwrite existing file");
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "kept");
+        redirects
+            .add(Fd::Single(1), "out", &out, Mode::Append, true)
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "kept");
+        redirects
+            .add(Fd::Single(1), "out", &out, Mode::Clobber, true)
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "");
+        // a new file is fine, and so is a device
+        redirects
+            .add(Fd::Single(1), "new", &dir.join("new"), Mode::Truncate, true)
+            .unwrap();
+        redirects
+            .add(
+                Fd::Single(1),
+                "/dev/null",
+                Path::new("/dev/null"),
+                Mode::Truncate,
+                true,
+            )
+            .unwrap();
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn duplicate_without_files() {
+        // `2>&1`
+        let mut redirects = Redirects::default();
+        redirects.duplicate(Fd::Single(2), "1").unwrap();
+        assert!(redirects.stderr_to_stdout && !redirects.stdout_to_stderr);
+
+        // `>&2 2>&1` leaves both on the original stderr
+        let mut redirects = Redirects::default();
+        redirects.duplicate(Fd::Single(1), "2").unwrap();
+        redirects.duplicate(Fd::Single(2), "1").unwrap();
+        assert!(redirects.stdout_to_stderr && !redirects.stderr_to_stdout);
+
+        // `1>&1`, and descriptors that are never passed on
+        let mut redirects = Redirects::default();
+        redirects.duplicate(Fd::Single(1), "1").unwrap();
+        redirects.duplicate(Fd::Single(3), "1").unwrap();
+        assert!(!redirects.stdout_to_stderr && !redirects.stderr_to_stdout);
+
+        for (fd, target) in [(Fd::Single(1), "5"), (Fd::Single(2), "x"), (Fd::Both, "1")] {
+            let error = Redirects::default().duplicate(fd, target).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!("{}: Bad file descriptor", target)
+            );
+        }
+    }
+
+    #[test]
+    fn duplicate_files() {
+        let dir = std::env::temp_dir().join(format!("redirect-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let out = dir.join("out");
+
+        // `>out 2>&1`, both streams share the file
+        let mut redirects = Redirects::default();
+        redirects
+            .add(Fd::Single(1), "out", &out, Mode::Truncate, false)
+            .unwrap();
+        redirects.duplicate(Fd::Single(2), "1").unwrap();
+        assert!(!redirects.stderr_to_stdout);
+        write!(redirects.stdout.as_ref().unwrap(), "one ").unwrap();
+        write!(redirects.stderr.as_ref().unwrap(), "two").unwrap();
+        assert_eq!(std::fs::read_to_string(&out).unwrap(), "one two");
+
+        // `2>&1 >out`, stderr still goes where stdout went before
+        let mut redirects = Redirects::default();
+        redirects.duplicate(Fd::Single(2), "1").unwrap();
+        redirects
+            .add(Fd::Single(1), "out", &out, Mode::Append, false)
+            .unwrap();
+        assert!(redirects.stderr_to_stdout && redirects.stderr.is_none());
+
+        // a later redirect of stdout undoes `>&2`
+        let mut redirects = Redirects::default();
+        redirects.duplicate(Fd::Single(1), "2").unwrap();
+        redirects
+            .add(Fd::Single(1), "out", &out, Mode::Truncate, false)
+            .unwrap();
+        assert!(!redirects.stdout_to_stderr && redirects.stdout.is_some());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,92 @@
+//! Keeps Ctrl-C from killing the shell while a foreground program runs,
+//! and names the signals programs die from.
+//!
+//! The terminal sends SIGINT to the whole foreground process group, so the
+//! programs started by the shell receive it on their own.
+
+use std::os::raw::c_int;
+
+use crate::sys;
+
+pub const SIGINT: c_int = 2;
+pub const SIGKILL: c_int = 9;
+pub const SIGSEGV: c_int = 11;
+pub const SIGTERM: c_int = 15;
+
+/// `SIG_DFL`, the default action of the signal.
+const DEFAULT: usize = 0;
+
+extern "C" fn ignore(_: c_int) {}
+
+/// What bash prints when a program was killed by the signal.
+///
+/// `None` for the rest, including SIGINT and SIGPIPE which bash never reports.
+pub fn describe(signal: c_int) -> Option<&'static str> {
+    match signal {
+        SIGKILL => Some("Killed"),
+        SIGSEGV => Some("Segmentation fault"),
+        SIGTERM => Some("Terminated"),
+        _ => None,
+    }
+}
+
+/// Catches SIGINT until the guard is dropped.
+///
+/// Unlike `SIG_IGN`, a handler is reset by `exec`, so the programs started in
+/// the meantime can still be interrupted.
+pub struct CatchInterrupt {
+    previous: usize,
+    interrupting: bool,
+}
+
+impl CatchInterrupt {
+    pub fn new() -> Self {
+        // SAFETY: the handler does nothing, so it is async-signal-safe
+        let previous = unsafe { sys::signal(SIGINT, ignore as extern "C" fn(c_int) as usize) };
+        Self {
+            previous,
+            interrupting: false,
+        }
+    }
+
+    /// Like [`CatchInterrupt::new`], but a `read` blocked when SIGINT arrives
+    /// fails with `EINTR` instead of being restarted.
+    pub fn interrupting() -> Self {
+        let mut guard = Self::new();
+        // SAFETY: only changes the flags of the handler installed above
+        guard.interrupting = unsafe { sys::siginterrupt(SIGINT, 1) } == 0;
+        guard
+    }
+}
+
+impl Drop for CatchInterrupt {
+    fn drop(&mut self) {
+        // SIG_ERR means nothing was installed in the first place
+        if self.interrupting {
+            // SAFETY: the handlers installed from now on restart reads again
+            unsafe { sys::siginterrupt(SIGINT, 0) };
+        }
+        let previous = if self.previous == usize::MAX {
+            DEFAULT
+        } else {
+            self.previous
+        };
+        // SAFETY: restores the handler that was installed before
+        unsafe { sys::signal(SIGINT, previous) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn descriptions() {
+        assert_eq!(describe(SIGSEGV), Some("Segmentation fault"));
+        assert_eq!(describe(SIGTERM), Some("Terminated"));
+        assert_eq!(describe(SIGKILL), Some("Killed"));
+        // bash stays quiet about these
+        assert_eq!(describe(SIGINT), None);
+        assert_eq!(describe(13), None);
+    }
+}
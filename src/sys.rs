@@ -0,0 +1,55 @@
+//! The few libc functions std does not wrap.
+//!
+//! They come from the libc std already links against, so no crate is needed.
+
+use std::os::raw::{c_char, c_int, c_long, c_uint};
+
+/// `time_t`, a `long` for the `localtime_r` of glibc, also where it is 32 bits.
+#[allow(non_camel_case_types)]
+pub type time_t = c_long;
+
+/// `struct tm` of glibc.
+#[repr(C)]
+pub struct Tm {
+    sec: c_int,
+    min: c_int,
+    hour: c_int,
+    mday: c_int,
+    mon: c_int,
+    year: c_int,
+    wday: c_int,
+    yday: c_int,
+    isdst: c_int,
+    gmtoff: c_long,
+    zone: *const c_char,
+}
+
+impl Default for Tm {
+    fn default() -> Self {
+        Self {
+            sec: 0,
+            min: 0,
+            hour: 0,
+            mday: 0,
+            mon: 0,
+            year: 0,
+            wday: 0,
+            yday: 0,
+            isdst: 0,
+            gmtoff: 0,
+            zone: std::ptr::null(),
+        }
+    }
+}
+
+/// The mode of [`access`] that checks for execute, or for a directory search, permission.
+pub const X_OK: c_int = 1;
+
+extern "C" {
+    pub fn access(path: *const c_char, mode: c_int) -> c_int;
+    pub fn geteuid() -> c_uint;
+    pub fn signal(signum: c_int, handler: usize) -> usize;
+    pub fn siginterrupt(signum: c_int, flag: c_int) -> c_int;
+    pub fn localtime_r(time: *const time_t, tm: *mut Tm) -> *mut Tm;
+    pub fn strftime(s: *mut c_char, max: usize, format: *const c_char, tm: *const Tm) -> usize;
+}
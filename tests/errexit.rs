@@ -0,0 +1,38 @@
+use std::process::{Command, Output};
+
+fn run(command: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_shell-starter-rust"))
+        .args(["-c", command])
+        .env_remove("SHELLOPTS")
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn stops_at_the_first_failure() {
+    let output = run("set -e; false; echo unreached");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert_eq!(output.status.code(), Some(1));
+
+    let output = run("set -o errexit\necho reached\nfalse\necho unreached");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "reached\n");
+    assert_eq!(output.status.code(), Some(1));
+}
+
+#[test]
+fn keeps_going_without_it() {
+    let output = run("false; echo reached");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "reached\n");
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn inherited_from_shellopts() {
+    let output = Command::new(env!("CARGO_BIN_EXE_shell-starter-rust"))
+        .args(["-c", "false; echo unreached"])
+        .env("SHELLOPTS", "errexit")
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert_eq!(output.status.code(), Some(1));
+}
@@ -0,0 +1,26 @@
+use std::{
+    os::unix::process::ExitStatusExt,
+    process::{Command, Output},
+};
+
+fn run(command: &str) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_shell-starter-rust"))
+        .args(["-c", command])
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn aborts_a_script() {
+    // the SIGINT the terminal would send to the whole foreground group
+    let output = run("sh -c 'kill -INT $PPID; sleep 1'; echo unreached");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert_eq!(output.status.signal(), Some(2));
+}
+
+#[test]
+fn interrupts_only_the_program() {
+    let output = run("sh -c 'kill -INT $$'; echo $?");
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "130\n");
+    assert_eq!(output.status.code(), Some(0));
+}
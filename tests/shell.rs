@@ -0,0 +1,570 @@
+use std::{os::unix::fs::PermissionsExt, path::PathBuf};
+
+use shell_starter_rust::{Completion, Entry, EntryKind, Errors, Shell};
+
+/// A fresh directory in the temp dir, removed on drop.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("shell-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        Self(dir)
+    }
+
+    /// Writes a file with the given permissions.
+    fn file(&self, name: &str, mode: u32) -> PathBuf {
+        let path = self.0.join(name);
+        std::fs::write(&path, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Runs the line, returning what it printed and its status.
+fn run(shell: &mut Shell, line: &str) -> (String, Result<i32, Errors>) {
+    let mut out = Vec::new();
+    let result = shell.run(line, &mut out);
+    (String::from_utf8(out).unwrap(), result)
+}
+
+fn output(shell: &mut Shell, line: &str) -> String {
+    let (out, result) = run(shell, line);
+    result.unwrap();
+    out
+}
+
+#[test]
+fn completion() {
+    let dir = TempDir::new("completion");
+    let program = dir.file("my-program", 0o755);
+    dir.file("not-a-program", 0o644);
+
+    let completion = Completion::new(&dir.0.display().to_string());
+    let entries: Vec<_> = completion.entries().collect();
+    for builtin in ["cd", "echo", "exit"] {
+        assert!(entries.contains(&&Entry {
+            name: builtin.to_string(),
+            kind: EntryKind::Builtin,
+        }));
+    }
+    assert!(entries.contains(&&Entry {
+        name: "my-program".to_string(),
+        kind: EntryKind::Program(program),
+    }));
+    assert!(!entries.iter().any(|entry| entry.name == "not-a-program"));
+    // sorted by name
+    assert!(entries.windows(2).all(|pair| pair[0].name < pair[1].name));
+}
+
+#[test]
+fn compgen() {
+    let dir = TempDir::new("compgen");
+    dir.file("echo-program", 0o755);
+    dir.file("notes.txt", 0o644);
+    std::fs::create_dir(dir.0.join("sub")).unwrap();
+    let mut shell = Shell::new();
+    output(&mut shell, &format!("PATH={0}; cd {0}", dir.0.display()));
+
+    assert_eq!(output(&mut shell, "compgen -b e"), "echo\nexit\n");
+    assert_eq!(
+        output(&mut shell, "compgen -c e"),
+        "echo\necho-program\nexit\n"
+    );
+    assert_eq!(output(&mut shell, "compgen -c nothing"), "");
+    assert_eq!(
+        output(&mut shell, "compgen -f"),
+        "echo-program\nnotes.txt\nsub\n"
+    );
+    assert_eq!(output(&mut shell, "compgen -f n"), "notes.txt\n");
+    let (out, result) = run(&mut shell, "compgen -x 2>&1");
+    assert!(out.starts_with("The incorrect argument -x"));
+    assert_eq!(result.unwrap(), 1);
+    assert_eq!(run(&mut shell, "compgen 2>&1").1.unwrap(), 1);
+}
+
+#[test]
+fn cd_to_file() {
+    let manifest = env!("CARGO_MANIFEST_DIR");
+    let mut shell = Shell::new();
+    output(&mut shell, &format!("cd {}", manifest));
+    assert_eq!(
+        output(&mut shell, "cd --to-file ./Cargo.toml; pwd"),
+        format!("{}\n", manifest)
+    );
+    assert_eq!(
+        output(&mut shell, "cd --to-file src/lib.rs; pwd"),
+        format!("{}/src\n", manifest)
+    );
+    // a directory is still changed into
+    assert_eq!(
+        output(&mut shell, "cd --to-file ..; pwd"),
+        format!("{}\n", manifest)
+    );
+}
+
+#[test]
+fn builtin_statuses() {
+    let mut shell = Shell::new();
+    assert_eq!(run(&mut shell, "set -o bogus 2>&1").1.unwrap(), 2);
+    assert_eq!(output(&mut shell, "echo $?"), "2\n");
+    assert_eq!(
+        output(&mut shell, "set -Z 2>&1; echo $?"),
+        "set: -Z: invalid option name\n2\n"
+    );
+    assert_eq!(
+        output(&mut shell, "type nothing-like-it 2>&1; echo $?"),
+        "nothing-like-it not found\n1\n"
+    );
+    assert_eq!(output(&mut shell, "echo; echo $?"), "\n0\n");
+}
+
+#[test]
+fn pwd_flags() {
+    let dir = TempDir::new("pwd");
+    std::fs::create_dir(dir.0.join("real")).unwrap();
+    std::os::unix::fs::symlink("real", dir.0.join("link")).unwrap();
+    let mut shell = Shell::new();
+    output(&mut shell, &format!("cd {}/link", dir.0.display()));
+
+    let link = format!("{}/link\n", dir.0.display());
+    let real = format!("{}/real\n", dir.0.display());
+    assert_eq!(output(&mut shell, "pwd"), link);
+    assert_eq!(output(&mut shell, "pwd -L"), link);
+    assert_eq!(output(&mut shell, "pwd -P"), real);
+    assert_eq!(output(&mut shell, "pwd -P -L"), link);
+    let (out, result) = run(&mut shell, "pwd -x 2>&1");
+    assert_eq!(
+        out,
+        "The incorrect argument -x should be a flag like -L or -P\n"
+    );
+    assert_eq!(result.unwrap(), 1);
+}
+
+#[test]
+fn cdprint() {
+    let mut shell = Shell::new();
+    assert_eq!(output(&mut shell, "cd /"), "");
+    output(&mut shell, "set -o cdprint");
+    assert_eq!(output(&mut shell, "cd /usr"), "/usr\n");
+    assert_eq!(output(&mut shell, "cd .."), "/\n");
+    // nothing is printed for a failed cd
+    assert_eq!(output(&mut shell, "cd /no-such-dir 2>/dev/null"), "");
+    output(&mut shell, "set +o cdprint");
+    assert_eq!(output(&mut shell, "cd /usr"), "");
+}
+
+#[test]
+fn noclobber() {
+    let dir = TempDir::new("noclobber");
+    let mut shell = Shell::new();
+    output(&mut shell, &format!("cd {}", dir.0.display()));
+
+    assert_eq!(
+        output(&mut shell, "echo one >out; echo two >out; cat out"),
+        "two\n"
+    );
+    output(&mut shell, "set -o noclobber");
+    let (_, result) = run(&mut shell, "echo three >out");
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "out: cannot overwrite existing file"
+    );
+    assert_eq!(
+        output(&mut shell, "echo four >>out; cat out"),
+        "two\nfour\n"
+    );
+    assert_eq!(output(&mut shell, "echo five >|out; cat out"), "five\n");
+}
+
+#[test]
+fn unreadable_path_directories() {
+    let dir = TempDir::new("unreadable");
+    let program = dir.file("reachable", 0o755);
+    let locked = TempDir::new("unreadable-locked");
+    locked.file("locked-away", 0o755);
+    std::fs::set_permissions(&locked.0, std::fs::Permissions::from_mode(0o000)).unwrap();
+    let not_a_dir = dir.file("not-a-dir", 0o644);
+
+    let path = format!(
+        "/no/such/dir:{}:{}:{}",
+        locked.0.display(),
+        not_a_dir.display(),
+        dir.0.display()
+    );
+    let completion = Completion::new(&path);
+    assert!(completion.entries().any(|entry| entry.name == "echo"));
+    assert!(completion
+        .entries()
+        .any(|entry| entry.kind == EntryKind::Program(program.clone())));
+    // root can read it anyway
+    if std::fs::read_dir(&locked.0).is_err() {
+        assert!(!completion
+            .entries()
+            .any(|entry| entry.name == "locked-away"));
+    }
+
+    std::fs::set_permissions(&locked.0, std::fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[test]
+fn redirect_anywhere() {
+    let dir = TempDir::new("redirect-anywhere");
+    let mut shell = Shell::new();
+    output(&mut shell, &format!("cd {}", dir.0.display()));
+
+    assert_eq!(
+        output(&mut shell, ">out echo a; echo b >>out c; cat out"),
+        "a\nb c\n"
+    );
+    // quoted, it is an argument
+    assert_eq!(output(&mut shell, "echo '>out' \\>out"), ">out >out\n");
+}
+
+#[test]
+fn multiple_redirects() {
+    let dir = TempDir::new("multiple-redirects");
+    let mut shell = Shell::new();
+    output(&mut shell, &format!("cd {}", dir.0.display()));
+
+    assert_eq!(
+        output(
+            &mut shell,
+            "ls no-such-file >out 2>err; cat out; cat err | wc -l | tr -d ' '"
+        ),
+        "1\n"
+    );
+    // the last redirect of a descriptor wins, the earlier files are still created
+    assert_eq!(
+        output(&mut shell, "echo a >first >second; cat first second"),
+        "a\n"
+    );
+}
+
+#[test]
+fn combined_redirects() {
+    let dir = TempDir::new("combined-redirects");
+    let mut shell = Shell::new();
+    output(&mut shell, &format!("cd {}", dir.0.display()));
+
+    assert_eq!(output(&mut shell, "echo a &>both"), "");
+    assert_eq!(
+        output(&mut shell, "ls no-such-file &>>both; echo b &>>both"),
+        ""
+    );
+    assert_eq!(output(&mut shell, "cat both | wc -l | tr -d ' '"), "3\n");
+    assert_eq!(output(&mut shell, "echo c &>both; cat both"), "c\n");
+}
+
+#[test]
+fn cd_failures() {
+    let dir = TempDir::new("cd-failures");
+    dir.file("file", 0o644);
+    let mut shell = Shell::new();
+    output(&mut shell, &format!("cd {}", dir.0.display()));
+    let before = output(&mut shell, "pwd");
+
+    let (out, result) = run(&mut shell, "cd file 2>&1");
+    assert_eq!(out, "cd: file: Not a directory\n");
+    assert_eq!(result.unwrap(), 1);
+    let (out, result) = run(&mut shell, "cd 'no \"such\" dir' 2>&1");
+    assert_eq!(out, "cd: no \"such\" dir: No such file or directory\n");
+    assert_eq!(result.unwrap(), 1);
+    assert_eq!(
+        output(&mut shell, "cd file/below 2>&1"),
+        "cd: file/below: Not a directory\n"
+    );
+    assert_eq!(output(&mut shell, "pwd"), before);
+}
+
+#[test]
+fn cd_needs_search_permission() {
+    let dir = TempDir::new("cd-permission");
+    let locked = dir.0.join("locked");
+    std::fs::create_dir_all(locked.join("inner")).unwrap();
+    std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o600)).unwrap();
+    let mut shell = Shell::new();
+    output(&mut shell, &format!("cd {}", dir.0.display()));
+
+    let (out, result) = run(&mut shell, "cd locked 2>&1");
+    // root may search it anyway, like bash lets it
+    if std::fs::metadata(locked.join("inner")).is_ok() {
+        assert_eq!(out, "");
+        assert_eq!(result.unwrap(), 0);
+        assert_eq!(output(&mut shell, "cd inner; echo $?"), "0\n");
+    } else {
+        assert_eq!(out, "cd: locked: Permission denied\n");
+        assert_eq!(result.unwrap(), 1);
+    }
+
+    std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+#[test]
+fn captures_builtins_and_programs() {
+    let mut shell = Shell::new();
+    assert_eq!(output(&mut shell, "echo a  'b  c'"), "a b  c\n");
+    assert_eq!(
+        output(&mut shell, "echo one; printf 'two\\n'"),
+        "one\ntwo\n"
+    );
+    assert_eq!(
+        output(&mut shell, "echo hello | tr a-z A-Z | cat"),
+        "HELLO\n"
+    );
+    assert_eq!(
+        output(&mut shell, "printf 'x\\ny\\n' | wc -l | tr -d ' '"),
+        "2\n"
+    );
+    assert_eq!(output(&mut shell, "type echo"), "echo is a shell builtin\n");
+}
+
+#[test]
+fn working_directory_is_per_shell() {
+    let mut first = Shell::new();
+    let mut second = Shell::new();
+    let before = output(&mut second, "pwd");
+    assert_eq!(output(&mut first, "cd /; pwd; sh -c pwd"), "/\n/\n");
+    assert_eq!(output(&mut first, "echo $PWD"), "/\n");
+    assert_eq!(output(&mut second, "pwd"), before);
+    assert_ne!(output(&mut second, "echo $PWD"), "/\n");
+}
+
+#[test]
+fn exit_is_left_to_the_caller() {
+    let mut shell = Shell::new();
+    assert_eq!(shell.exit_requested(), None);
+    let (out, result) = run(&mut shell, "echo before; exit 3; echo after");
+    assert_eq!(out, "before\n");
+    assert_eq!(result.unwrap(), 3);
+    assert_eq!(shell.exit_requested(), Some(3));
+
+    // like in bash, a stage of a pipeline can't end the shell
+    let mut shell = Shell::new();
+    assert_eq!(output(&mut shell, "exit 4 | cat"), "");
+    assert_eq!(shell.exit_requested(), None);
+}
+
+#[test]
+fn returns_errors() {
+    let mut shell = Shell::new();
+    let (_, result) = run(&mut shell, "no-such-command-anywhere");
+    assert!(matches!(result, Err(Errors::CommandNotFound(_))));
+    assert_eq!(output(&mut shell, "echo $?"), "127\n");
+
+    let (_, result) = run(&mut shell, "echo 'open");
+    assert_eq!(result.unwrap_err().exit_code(), 2);
+    let (_, result) = run(&mut shell, "echo ${UNSET:?gone}");
+    assert_eq!(result.unwrap_err().to_string(), "UNSET: gone");
+    assert!(shell.take_errors().is_empty());
+
+    // the line goes on after an earlier pipeline failed
+    let (out, result) = run(&mut shell, "no-such-command-anywhere; echo $?; ${:}");
+    assert_eq!(out, "127\n");
+    assert!(matches!(result, Err(Errors::Expand(_))));
+    let errors = shell.take_errors();
+    assert!(matches!(
+        errors.as_slice(),
+        [Errors::CommandNotFound(command)] if command == "no-such-command-anywhere"
+    ));
+    assert!(shell.take_errors().is_empty());
+}
+
+#[test]
+fn process_args() {
+    let mut shell = Shell::new();
+    output(&mut shell, "X='1 2' HOME=/home/me");
+    let args = shell
+        .state_mut()
+        .process_args("A=b echo ${X}{a,b} | wc -l; cd ~")
+        .unwrap();
+    assert_eq!(
+        args,
+        [
+            vec!["echo", "1", "2a", "1", "2b"],
+            vec!["wc", "-l"],
+            vec!["cd", "/home/me"],
+        ]
+    );
+}
+
+#[test]
+fn non_utf8_file_names() {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    let dir = TempDir::new("non-utf8");
+    let good = dir.file("good", 0o755);
+    let bad = dir.0.join(OsStr::from_bytes(b"bad-\xff"));
+    std::fs::write(&bad, "").unwrap();
+    std::fs::set_permissions(&bad, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    // only the name that can't be completed is skipped
+    let completion = Completion::new(&dir.0.display().to_string());
+    let programs: Vec<_> = completion
+        .entries()
+        .filter(|entry| entry.kind != EntryKind::Builtin)
+        .collect();
+    assert_eq!(
+        programs,
+        [&Entry {
+            name: "good".to_string(),
+            kind: EntryKind::Program(good),
+        }]
+    );
+
+    let mut shell = Shell::new();
+    output(&mut shell, &format!("PATH={0}; cd {0}", dir.0.display()));
+    assert_eq!(output(&mut shell, "compgen -c goo"), "good\n");
+    assert_eq!(output(&mut shell, "compgen -f"), "good\n");
+}
+
+#[test]
+fn killed_by_a_signal() {
+    let mut shell = Shell::new();
+    assert_eq!(run(&mut shell, "sh -c 'kill -SEGV $$'").1.unwrap(), 139);
+    assert_eq!(
+        output(&mut shell, "sh -c 'kill -TERM $$'; echo $?"),
+        "143\n"
+    );
+    assert_eq!(
+        output(&mut shell, "sh -c 'kill -KILL $$' | cat; echo $?"),
+        "0\n"
+    );
+    output(&mut shell, "set -o pipefail");
+    assert_eq!(
+        output(&mut shell, "sh -c 'kill -KILL $$' | cat; echo $?"),
+        "137\n"
+    );
+}
+
+#[test]
+fn pipefail() {
+    let mut shell = Shell::new();
+    assert_eq!(run(&mut shell, "false | true").1.unwrap(), 0);
+    output(&mut shell, "set -o pipefail");
+    assert_eq!(run(&mut shell, "false | true").1.unwrap(), 1);
+    // the last stage that failed
+    assert_eq!(
+        run(&mut shell, "sh -c 'exit 2' | sh -c 'exit 3' | true")
+            .1
+            .unwrap(),
+        3
+    );
+    assert_eq!(run(&mut shell, "true | true").1.unwrap(), 0);
+    output(&mut shell, "set +o pipefail");
+    assert_eq!(run(&mut shell, "false | true").1.unwrap(), 0);
+}
+
+#[test]
+fn pwd_prints_the_path_as_is() {
+    let dir = TempDir::new("pwd-as-is");
+    let odd = dir.0.join("a \"quoted\" \\ tab\tdir é");
+    std::fs::create_dir(&odd).unwrap();
+    let mut shell = Shell::new();
+    output(&mut shell, &format!("cd {}", dir.0.display()));
+    output(&mut shell, "cd 'a \"quoted\" \\ tab\tdir é'");
+    assert_eq!(output(&mut shell, "pwd"), format!("{}\n", odd.display()));
+    assert_eq!(output(&mut shell, "pwd -P"), format!("{}\n", odd.display()));
+}
+
+#[test]
+fn keeps_state_between_lines() {
+    let mut shell = Shell::new();
+    assert_eq!(output(&mut shell, "X='a b'"), "");
+    assert_eq!(output(&mut shell, "printf '[%s]' $X \"$X\""), "[a][b][a b]");
+    assert_eq!(run(&mut shell, "false").1.unwrap(), 1);
+    assert_eq!(output(&mut shell, "echo $?"), "1\n");
+    // only for the one command, not for the shell
+    assert_eq!(
+        output(&mut shell, "Y=temp sh -c 'echo $Y'; echo \"[$Y]\""),
+        "temp\n[]\n"
+    );
+}
+
+#[test]
+fn assignments_see_the_earlier_ones() {
+    let mut shell = Shell::new();
+    assert_eq!(
+        output(&mut shell, "a=1 b=$a sh -c 'echo $a $b'; echo \"[$a$b]\""),
+        "1 1\n[]\n"
+    );
+    assert_eq!(output(&mut shell, "a=1 b=$a; echo $a $b"), "1 1\n");
+    // a shell variable is shadowed for the command, then comes back unexported
+    assert_eq!(
+        output(&mut shell, "a=2 b=${a}2 sh -c 'echo $a $b'; echo $a $b"),
+        "2 22\n1 1\n"
+    );
+    assert_eq!(output(&mut shell, "sh -c 'echo \"[$a]\"'"), "[]\n");
+    // the arguments are expanded before the assignments
+    assert_eq!(output(&mut shell, "a=3 echo $a"), "1\n");
+    assert_eq!(output(&mut shell, "a=4 a=${a}5 sh -c 'echo $a'"), "45\n");
+    // with a builtin as well
+    assert_eq!(
+        output(&mut shell, "a=5 b=${a}0 set | grep '^[ab]='; echo $a $b"),
+        "a=5\nb=50\n1 1\n"
+    );
+}
+
+#[test]
+fn here_documents() {
+    let mut shell = Shell::new();
+    output(&mut shell, "X=value");
+    assert_eq!(
+        output(&mut shell, "cat <<EOF\n$X \"q\"\n  kept\nEOF"),
+        "value \"q\"\n  kept\n"
+    );
+    assert_eq!(output(&mut shell, "cat <<'EOF'\n$X\nEOF"), "$X\n");
+    assert_eq!(output(&mut shell, "cat <<-END\n\t\tx\n\tEND"), "x\n");
+    assert_eq!(
+        output(
+            &mut shell,
+            "echo ignored | cat <<EOF | tr a-z A-Z\nhere\nEOF"
+        ),
+        "HERE\n"
+    );
+}
+
+#[test]
+fn here_strings() {
+    let mut shell = Shell::new();
+    output(&mut shell, "X='a  b'");
+    assert_eq!(output(&mut shell, "cat <<< here"), "here\n");
+    assert_eq!(output(&mut shell, "cat <<< \"$X\""), "a  b\n");
+    // like bash, the word is not split
+    assert_eq!(output(&mut shell, "cat <<< $X"), "a  b\n");
+    assert_eq!(output(&mut shell, "wc -c <<< '' | tr -d ' '"), "1\n");
+}
+
+#[test]
+fn stderr_into_the_pipe() {
+    let dir = TempDir::new("stderr-pipe");
+    let mut shell = Shell::new();
+    output(&mut shell, &format!("cd {}", dir.0.display()));
+
+    assert_eq!(
+        output(&mut shell, "ls no-such-file 2>&1 | wc -l | tr -d ' '"),
+        "1\n"
+    );
+    assert_eq!(
+        output(&mut shell, "type nothing-like-it 2>&1 | tr a-z A-Z"),
+        "NOTHING-LIKE-IT NOT FOUND\n"
+    );
+    // stderr goes where stdout went before it was redirected
+    assert_eq!(
+        output(&mut shell, "ls no-such-file 2>&1 >out | wc -l | tr -d ' '"),
+        "1\n"
+    );
+    assert_eq!(
+        output(
+            &mut shell,
+            "ls no-such-file >out 2>&1 | wc -l | tr -d ' '; cat out | wc -l | tr -d ' '"
+        ),
+        "0\n1\n"
+    );
+}